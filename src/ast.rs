@@ -1,4 +1,4 @@
-use crate::lexer::TokenType;
+use crate::lexer::{Span, TokenType};
 
 #[derive(Debug, Copy, Clone)]
 pub enum UnaryOperator {
@@ -13,6 +13,16 @@ pub enum BinaryOperator {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    IntegerDivide,
+    Power,
+
+    // Bitwise, on whole numbers
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
 
     // Boolean
     Equal,
@@ -32,24 +42,38 @@ pub enum LogicalOperator {
 #[derive(Debug, Clone)]
 pub enum Declaration {
     Statement(Statement),
-    Procedure(String, Vec<Statement>), // Name, Code
+    Procedure(String, Vec<String>, Vec<Statement>), // Name, Parameters, Code
+    Import(String, Span),                           // Path relative to the importing file
+    Constant(String, Expression, Span),             // Name, Value
 }
 
 #[derive(Debug, Clone)]
-pub enum Statement {
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span, // Reported when the statement fails to compile or run
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementKind {
     VariableAssignment(String, Expression), // Name, Value
+    Let(String, Expression), // Name, Value of a local that lives until the end of its block
+    IndexAssignment(Expression, Expression, Expression), // List or map, Index or key, Value
     Expression(Expression),
     Print(Expression),
     Block(Vec<Statement>),
-    If(Expression, Box<Statement>, Option<Box<Statement>>), // Condition, Then, Else
-    ProcedureCall(String),                                  // Name
-    Gamble(Expression),                                     // Amount to gamble
-    Buy(Expression, Expression),                            // Stock, Amount
-    Sell(Expression, Expression),                           // Stock, Amount
-    Loan(Expression),                                       // Take out loan for amount
-    Pay(Expression),                                        // Amount to pay back loan
-    While(Expression, Box<Statement>),                      // Condition, Body
+    If(Vec<(Expression, Statement)>, Option<Box<Statement>>), // Conditions and branches of the if and elifs, Else
+    ProcedureCall(String, Vec<Expression>),                   // Name, Arguments
+    Return(Expression),                                       // Value to return
+    Break,
+    Continue,
+    Gamble(Expression),                // Amount to gamble
+    Buy(Expression, Expression),       // Stock, Amount
+    Sell(Expression, Expression),      // Stock, Amount
+    Loan(Expression),                  // Take out loan for amount
+    Pay(Expression),                   // Amount to pay back loan
+    While(Expression, Box<Statement>), // Condition, Body
     Range(String, Expression, Expression, Expression, Box<Statement>), // Variable name, Start, End, Step, Body
+    ForEach(String, Expression, Box<Statement>), // Variable name, Collection, Body
     Work,
 }
 
@@ -62,9 +86,27 @@ pub enum Expression {
     Variable(String),
     ReadonlyVariable(String), // Used internally for economy variables, like @balance
     StockPrice(String),       // Used to access stock prices from inside the program
+    Input(bool),              // Reads a line, as a number when true
+    Call(String, Vec<Expression>), // Procedure name, Arguments
+    List(Vec<Expression>),    // Elements
+    Map(Vec<(Expression, Expression)>), // Keys and values
+    Index(Box<Expression>, Box<Expression>), // List, map or string, Index or key
     Unary(UnaryOperator, Box<Expression>), // Operator, Operand
     Binary(BinaryOperator, Box<Expression>, Box<Expression>), // Operator, Left, Right
     Logical(LogicalOperator, Box<Expression>, Box<Expression>), // Operator, Left, Right
+    Interpolation(Vec<InterpolationPart>), // Parts of a string with embedded expressions, joined at runtime
+}
+
+#[derive(Debug, Clone)]
+pub enum InterpolationPart {
+    Literal(String),
+    Expression(Expression, Option<usize>), // Value, Number of decimals from a format spec like {x:.2}
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Statement { kind, span }
+    }
 }
 
 impl UnaryOperator {
@@ -84,6 +126,14 @@ impl BinaryOperator {
             TokenType::Minus => Self::Subtract,
             TokenType::Star => Self::Multiply,
             TokenType::Slash => Self::Divide,
+            TokenType::Percent => Self::Modulo,
+            TokenType::Div => Self::IntegerDivide,
+            TokenType::StarStar => Self::Power,
+            TokenType::Ampersand => Self::BitAnd,
+            TokenType::Pipe => Self::BitOr,
+            TokenType::Caret => Self::BitXor,
+            TokenType::LessLess => Self::ShiftLeft,
+            TokenType::GreaterGreater => Self::ShiftRight,
             TokenType::Equal => Self::Equal,
             TokenType::BangEqual => Self::NotEqual,
             TokenType::Greater => Self::Greater,
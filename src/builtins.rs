@@ -0,0 +1,288 @@
+use crate::{economy::Costs, value::Value};
+
+// Functions built into the language, called like procedures: `len(xs)`, `call push(xs, 1)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Len,
+    Push,
+    Pop,
+    Get,
+    Has,
+    Delete,
+    Keys,
+    Upper,
+    Lower,
+    Trim,
+    Substring,
+    Split,
+    Contains,
+    Number,
+    Format,
+    ReadFile,
+    WriteFile,
+    AppendFile,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "len" => Some(Builtin::Len),
+            "push" => Some(Builtin::Push),
+            "pop" => Some(Builtin::Pop),
+            "get" => Some(Builtin::Get),
+            "has" => Some(Builtin::Has),
+            "delete" => Some(Builtin::Delete),
+            "keys" => Some(Builtin::Keys),
+            "upper" => Some(Builtin::Upper),
+            "lower" => Some(Builtin::Lower),
+            "trim" => Some(Builtin::Trim),
+            "substring" => Some(Builtin::Substring),
+            "split" => Some(Builtin::Split),
+            "contains" => Some(Builtin::Contains),
+            "number" => Some(Builtin::Number),
+            "format" => Some(Builtin::Format),
+            "read_file" => Some(Builtin::ReadFile),
+            "write_file" => Some(Builtin::WriteFile),
+            "append_file" => Some(Builtin::AppendFile),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Len => "len",
+            Builtin::Push => "push",
+            Builtin::Pop => "pop",
+            Builtin::Get => "get",
+            Builtin::Has => "has",
+            Builtin::Delete => "delete",
+            Builtin::Keys => "keys",
+            Builtin::Upper => "upper",
+            Builtin::Lower => "lower",
+            Builtin::Trim => "trim",
+            Builtin::Substring => "substring",
+            Builtin::Split => "split",
+            Builtin::Contains => "contains",
+            Builtin::Number => "number",
+            Builtin::Format => "format",
+            Builtin::ReadFile => "read_file",
+            Builtin::WriteFile => "write_file",
+            Builtin::AppendFile => "append_file",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Len
+            | Builtin::Pop
+            | Builtin::Keys
+            | Builtin::Upper
+            | Builtin::Lower
+            | Builtin::Trim
+            | Builtin::Number
+            | Builtin::ReadFile => 1,
+            Builtin::Push
+            | Builtin::Get
+            | Builtin::Has
+            | Builtin::Delete
+            | Builtin::Split
+            | Builtin::Contains
+            | Builtin::Format
+            | Builtin::WriteFile
+            | Builtin::AppendFile => 2,
+            Builtin::Substring => 3,
+        }
+    }
+
+    // Describes the arguments, for type errors
+    fn expects(&self) -> &'static str {
+        match self {
+            Builtin::Len => "a list, map or string",
+            Builtin::Push | Builtin::Pop => "a list",
+            Builtin::Get | Builtin::Has | Builtin::Delete => "a map and a string key",
+            Builtin::Keys => "a map",
+            Builtin::Upper | Builtin::Lower | Builtin::Trim | Builtin::Number => "a string",
+            Builtin::Substring => "a string and two numbers",
+            Builtin::Split => "two strings",
+            Builtin::Contains => "two strings, or a list and a value",
+            Builtin::Format => "two numbers",
+            Builtin::ReadFile => "a path",
+            Builtin::WriteFile | Builtin::AppendFile => "a path and a string",
+        }
+    }
+
+    // File functions need the VM's sandbox, so the VM runs them instead of call
+    pub fn is_file(&self) -> bool {
+        matches!(
+            self,
+            Builtin::ReadFile | Builtin::WriteFile | Builtin::AppendFile
+        )
+    }
+
+    // Price of a call on top of the flat builtin_call cost, depends on the arguments
+    // File functions are billed by the VM once it knows how much was transferred
+    pub fn cost(&self, arguments: &[Value], costs: &Costs) -> f64 {
+        match (self, arguments) {
+            (Builtin::Push, _) => costs.element,
+            // keys builds a new list, so every key is stored again
+            (Builtin::Keys, [Value::Map(entries)]) => costs.element * entries.borrow().len() as f64,
+            // split also stores every part in a new list
+            (Builtin::Split, [Value::String(s), Value::String(separator)])
+                if !separator.is_empty() =>
+            {
+                let parts = s.split(separator.as_str()).count();
+                costs.character * characters(arguments) + costs.element * parts as f64
+            }
+            // Other string functions cost more the longer their strings are
+            (
+                Builtin::Upper
+                | Builtin::Lower
+                | Builtin::Trim
+                | Builtin::Substring
+                | Builtin::Contains
+                | Builtin::Number,
+                _,
+            ) => costs.character * characters(arguments),
+            _ => 0.0,
+        }
+    }
+
+    // Runs the builtin, errors are reported as runtime errors by the VM
+    pub fn call(&self, arguments: &[Value]) -> Result<Value, String> {
+        match (self, arguments) {
+            (Builtin::Len, [Value::List(items)]) => Ok(Value::Number(items.borrow().len() as f64)),
+            (Builtin::Len, [Value::Map(entries)]) => {
+                Ok(Value::Number(entries.borrow().len() as f64))
+            }
+            (Builtin::Len, [Value::String(s)]) => Ok(Value::Number(s.chars().count() as f64)),
+            (Builtin::Push, [list @ Value::List(_), value]) if value.reaches(list) => {
+                Err("Cannot push a list into itself".to_string())
+            }
+            (Builtin::Push, [Value::List(items), value]) => {
+                items.borrow_mut().push(value.clone());
+                Ok(Value::Void)
+            }
+            (Builtin::Pop, [Value::List(items)]) => items
+                .borrow_mut()
+                .pop()
+                .ok_or_else(|| "Cannot pop from an empty list".to_string()),
+            // Missing keys give void, unlike indexing which fails
+            (Builtin::Get, [Value::Map(entries), Value::String(key)]) => {
+                Ok(entries.borrow().get(key).cloned().unwrap_or(Value::Void))
+            }
+            (Builtin::Has, [Value::Map(entries), Value::String(key)]) => {
+                Ok(Value::Boolean(entries.borrow().contains_key(key)))
+            }
+            // Gives back whether the key was there
+            (Builtin::Delete, [Value::Map(entries), Value::String(key)]) => {
+                Ok(Value::Boolean(entries.borrow_mut().remove(key).is_some()))
+            }
+            (Builtin::Keys, [Value::Map(entries)]) => Ok(Value::from_list(
+                entries
+                    .borrow()
+                    .keys()
+                    .map(|key| Value::String(key.clone()))
+                    .collect(),
+            )),
+            (Builtin::Upper, [Value::String(s)]) => Ok(Value::String(s.to_uppercase())),
+            (Builtin::Lower, [Value::String(s)]) => Ok(Value::String(s.to_lowercase())),
+            (Builtin::Trim, [Value::String(s)]) => Ok(Value::String(s.trim().to_string())),
+            // Characters from start up to, but not including, end
+            (Builtin::Substring, [Value::String(s), start, end]) => {
+                let length = s.chars().count();
+                let end = bound(end, length, "End")?;
+                let start = bound(start, end, "Start")?;
+
+                Ok(Value::String(
+                    s.chars().skip(start).take(end - start).collect(),
+                ))
+            }
+            (Builtin::Split, [Value::String(_), Value::String(separator)])
+                if separator.is_empty() =>
+            {
+                Err("Cannot split on an empty separator".to_string())
+            }
+            (Builtin::Split, [Value::String(s), Value::String(separator)]) => Ok(Value::from_list(
+                s.split(separator.as_str())
+                    .map(Value::from_string)
+                    .collect(),
+            )),
+            (Builtin::Contains, [Value::String(s), Value::String(part)]) => {
+                Ok(Value::Boolean(s.contains(part.as_str())))
+            }
+            (Builtin::Contains, [Value::List(items), value]) => {
+                Ok(Value::Boolean(items.borrow().contains(value)))
+            }
+            (Builtin::Number, [Value::String(s)]) => match s.trim().parse::<f64>() {
+                Ok(n) if n.is_finite() => Ok(Value::Number(n)),
+                _ => Err(format!("Cannot convert '{}' to a number", s)),
+            },
+            // Fixed number of decimals, for money: format(@balance, 2)
+            (Builtin::Format, [Value::Number(n), decimals]) => {
+                let decimals = bound(decimals, 20, "Decimals")?;
+                Ok(Value::String(format!("{:.*}", decimals, n)))
+            }
+            (_, arguments) => Err(self.type_error(arguments)),
+        }
+    }
+
+    pub fn type_error(&self, arguments: &[Value]) -> String {
+        let types: Vec<&str> = arguments.iter().map(Value::type_name).collect();
+
+        format!(
+            "{} expects {}, got {}",
+            self.name(),
+            self.expects(),
+            types.join(" and ")
+        )
+    }
+}
+
+// Checks an index against a collection's length, indices must be whole numbers
+pub fn index(index: &Value, length: usize) -> Result<usize, String> {
+    match index {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && (*n as usize) < length => {
+            Ok(*n as usize)
+        }
+        Value::Number(n) => Err(format!("Index {} is out of range for length {}", n, length)),
+        value => Err(format!("Index must be a number, got {}", value.type_name())),
+    }
+}
+
+// Like index, but max itself is allowed, for the end of a range or a count
+fn bound(value: &Value, max: usize, name: &str) -> Result<usize, String> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= max as f64 => Ok(*n as usize),
+        Value::Number(n) => Err(format!(
+            "{} must be a whole number from 0 to {}, got {}",
+            name, max, n
+        )),
+        value => Err(format!(
+            "{} must be a number, got {}",
+            name,
+            value.type_name()
+        )),
+    }
+}
+
+// Total length of the string arguments, which string functions are priced by
+fn characters(arguments: &[Value]) -> f64 {
+    arguments
+        .iter()
+        .map(|argument| match argument {
+            Value::String(s) => s.chars().count() as f64,
+            _ => 0.0,
+        })
+        .sum()
+}
+
+// Map keys are always strings
+pub fn key(key: &Value) -> Result<&str, String> {
+    match key {
+        Value::String(key) => Ok(key),
+        value => Err(format!(
+            "Map keys must be strings, got {}",
+            value.type_name()
+        )),
+    }
+}
@@ -1,14 +1,45 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
 use crate::{
     ast::*,
+    builtins::Builtin,
+    error::MonopolangError,
+    lexer::{self, Lexer, Span},
+    parser::Parser,
     value::Value,
     vm::{OpCode, VM},
 };
 
-#[derive(Debug, Clone)]
 pub struct Compiler {
     ast: Vec<Declaration>,
     pub vm: VM,
-    pub parent: Option<Box<Compiler>>,
+    locals: Vec<String>, // Names of the stack slots in the procedure or blocks being compiled
+    in_procedure: bool,
+    loops: Vec<Loop>,   // Loops around the statement being compiled, innermost last
+    scopes: Vec<usize>, // Locals in scope when each enclosing block started, innermost last
+    span: Span,         // Span of the statement being compiled, attached to every emitted op
+    directory: PathBuf, // Imports in the code being compiled are resolved relative to this
+    modules: Vec<(PathBuf, String)>, // Canonical path and name of every module loaded so far
+    importing: Vec<PathBuf>, // Modules whose imports are being loaded, to detect cycles
+    module: Option<Module>, // Module being compiled, None for the program itself
+    constants: HashMap<String, Option<Value>>, // Value of every constant known at compile time, None when computed at runtime
+}
+
+// Procedures of a module are named after it, like helpers.safe_work
+struct Module {
+    name: String,
+    procedures: HashSet<String>, // Unqualified names, calls to them from inside the module get the prefix
+}
+
+// Jumps out of a loop that can only be patched once the whole loop is compiled
+struct Loop {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+    locals: usize, // Locals in scope when the body starts
 }
 
 impl Compiler {
@@ -16,216 +47,884 @@ impl Compiler {
         Compiler {
             ast,
             vm,
-            parent: None,
+            locals: Vec::new(),
+            in_procedure: false,
+            loops: Vec::new(),
+            scopes: Vec::new(),
+            span: Span::default(),
+            directory: PathBuf::from("."),
+            modules: Vec::new(),
+            importing: Vec::new(),
+            module: None,
+            constants: HashMap::new(),
+        }
+    }
+
+    // Resolves imports relative to the given directory instead of the current one
+    pub fn in_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.directory = directory.into();
+        self
+    }
+
+    // Marks the file being compiled as already importing, so importing it back is reported as a cycle
+    pub fn in_file(mut self, file: &Path) -> Self {
+        if let Ok(canonical) = file.canonicalize() {
+            self.importing.push(canonical);
         }
+        self
     }
 
-    pub fn compile(&mut self) {
+    pub fn compile(&mut self) -> Result<(), MonopolangError> {
         for decl in self.ast.clone() {
-            self.declaration(decl);
+            self.declaration(decl)?;
         }
+
+        Ok(())
     }
 
-    fn declaration(&mut self, decl: Declaration) {
+    // Compiles more declarations onto the end of the same VM, used by the REPL to keep state between inputs
+    // Nothing from a declaration that fails to compile is kept
+    pub fn compile_more(&mut self, ast: Vec<Declaration>) -> Result<(), MonopolangError> {
+        let code_len = self.vm.code.len();
+        let procedures = self.vm.procedures.clone();
+        let modules = self.modules.len();
+        let constants = self.constants.clone();
+
+        self.ast = ast;
+
+        if let Err(err) = self.compile() {
+            self.vm.code.truncate(code_len);
+            self.vm.spans.truncate(code_len);
+            self.vm.procedures = procedures;
+            self.modules.truncate(modules);
+            self.constants = constants;
+            self.locals.clear();
+            self.in_procedure = false;
+            self.loops.clear();
+            self.scopes.clear();
+
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    fn declaration(&mut self, decl: Declaration) -> Result<(), MonopolangError> {
         match decl {
             Declaration::Statement(stmt) => self.statement(stmt),
-            Declaration::Procedure(name, code) => self.procedure(name, code),
+            Declaration::Procedure(name, parameters, code) => {
+                self.procedure(name, parameters, code)
+            }
+            Declaration::Import(path, span) => {
+                self.span = span;
+                self.import(path)
+            }
+            Declaration::Constant(name, value, span) => {
+                self.span = span;
+                self.constant(name, value)
+            }
         }
     }
 
-    fn procedure(&mut self, name: String, code: Vec<Statement>) {
-        // Create a new compiler for the procedure, with the current compiler as the parent
-        let mut compiler = Compiler {
-            ast: code
-                .into_iter()
-                .map(|stmt| Declaration::Statement(stmt))
-                .collect(),
-            vm: VM::new(),
-            parent: Some(Box::new(self.clone())),
-        };
+    // Constants are computed while compiling when their value only depends on literals and other constants,
+    // reads then use the value directly. Either way the global is set too, for procedures compiled earlier
+    fn constant(&mut self, name: String, value: Expression) -> Result<(), MonopolangError> {
+        if self.constants.contains_key(&name) {
+            return Err(self.error(&format!("Constant '{}' is already defined", name)));
+        }
+
+        self.op_cost(self.vm.economy.costs.constant);
+
+        let known = self.evaluate(&value);
+        match &known {
+            Some(value) => {
+                let idx = self.write_constant(value.clone());
+                self.write_op(OpCode::Constant(idx));
+            }
+            None => self.expression(value)?,
+        }
+        self.write_op(OpCode::DefineConstant(name.clone()));
 
-        compiler.compile();
+        self.constants.insert(name, known);
 
-        self.vm.constants = compiler.parent.unwrap().vm.constants.clone();
-        self.vm.procedures.insert(name, compiler.vm.code);
+        Ok(())
     }
 
-    fn statement(&mut self, stmt: Statement) {
-        match stmt {
-            Statement::Print(expr) => {
-                self.op_cost(1);
-                self.expression(expr);
-                self.vm.write_op(OpCode::Print);
+    // Value of an expression at compile time, None when it needs the program to run
+    // Operators in it are free, as they never run
+    fn evaluate(&self, expression: &Expression) -> Option<Value> {
+        match expression {
+            Expression::Number(n) => Some(Value::Number(*n)),
+            Expression::String(s) => Some(Value::String(s.clone())),
+            Expression::Boolean(b) => Some(Value::Boolean(*b)),
+            Expression::Void => Some(Value::Void),
+            Expression::Variable(name) if self.resolve_local(name).is_none() => {
+                self.constants.get(name).cloned().flatten()
             }
-            Statement::Expression(expr) => {
-                self.expression(expr);
-                self.vm.write_op(OpCode::Pop);
+            Expression::Unary(UnaryOperator::Negate, operand) => match self.evaluate(operand)? {
+                Value::Number(n) => Some(Value::Number(-n)),
+                _ => None,
+            },
+            Expression::Unary(UnaryOperator::Not, operand) => {
+                Some(Value::Boolean(!self.evaluate(operand)?.is_truthy()))
             }
-            Statement::VariableAssignment(name, expr) => {
-                self.op_cost(2);
-                self.expression(expr);
-                self.vm.write_op(OpCode::SetGlobal(name));
+            Expression::Binary(op, left, right) => {
+                match (op, self.evaluate(left)?, self.evaluate(right)?) {
+                    (BinaryOperator::Add, Value::Number(a), Value::Number(b)) => {
+                        Some(Value::Number(a + b))
+                    }
+                    (BinaryOperator::Add, Value::String(a), Value::String(b)) => {
+                        Some(Value::String(a + &b))
+                    }
+                    (BinaryOperator::Add, Value::String(a), Value::Number(b)) => {
+                        Some(Value::String(format!("{}{}", a, b)))
+                    }
+                    (BinaryOperator::Add, Value::Number(a), Value::String(b)) => {
+                        Some(Value::String(format!("{}{}", a, b)))
+                    }
+                    (BinaryOperator::Subtract, Value::Number(a), Value::Number(b)) => {
+                        Some(Value::Number(a - b))
+                    }
+                    (BinaryOperator::Multiply, Value::Number(a), Value::Number(b)) => {
+                        Some(Value::Number(a * b))
+                    }
+                    (BinaryOperator::Divide, Value::Number(a), Value::Number(b)) => {
+                        Some(Value::Number(a / b))
+                    }
+                    (BinaryOperator::Power, Value::Number(a), Value::Number(b)) => {
+                        Some(Value::Number(a.powf(b)))
+                    }
+                    // Everything else is left to the VM, which reports errors with the right span
+                    _ => None,
+                }
             }
-            Statement::Block(stmts) => {
+            Expression::Interpolation(parts) => {
+                let mut joined = String::new();
+
+                for part in parts {
+                    match part {
+                        InterpolationPart::Literal(text) => joined += text,
+                        InterpolationPart::Expression(expression, None) => {
+                            joined += &self.evaluate(expression)?.format()
+                        }
+                        InterpolationPart::Expression(expression, Some(decimals)) => {
+                            match self.evaluate(expression)? {
+                                Value::Number(n) => joined += &format!("{:.*}", decimals, n),
+                                _ => return None,
+                            }
+                        }
+                    }
+                }
+
+                Some(Value::String(joined))
+            }
+            _ => None,
+        }
+    }
+
+    // Compiles a module where it is imported, so its top level code runs there once, after paying the licensing fee
+    // Importing the same module again does nothing
+    fn import(&mut self, path: String) -> Result<(), MonopolangError> {
+        let file = self.directory.join(&path);
+        let canonical = file
+            .canonicalize()
+            .map_err(|err| self.io_error(&format!("Cannot import '{}': {}", path, err)))?;
+
+        if let Some(start) = self
+            .importing
+            .iter()
+            .position(|module| *module == canonical)
+        {
+            let cycle: Vec<String> = self.importing[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|module| display_name(module))
+                .collect();
+
+            return Err(self.error(&format!("Import cycle: {}", cycle.join(" -> "))));
+        }
+        if self.modules.iter().any(|(module, _)| *module == canonical) {
+            return Ok(());
+        }
+
+        let name = display_name(&canonical);
+        let name = name.strip_suffix(".mp").unwrap_or(&name).to_string();
+        // Same rule as identifiers in the lexer, or the module's procedures couldn't be called
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphabetic() || c == '_');
+
+        if !valid {
+            return Err(self.error(&format!(
+                "Cannot import '{}', '{}' is not a valid module name",
+                path, name
+            )));
+        }
+        if lexer::keyword(&name).is_some() {
+            return Err(self.error(&format!(
+                "Cannot import '{}', '{}' is a reserved word and can't name a module",
+                path, name
+            )));
+        }
+        if self.modules.iter().any(|(_, module)| *module == name) {
+            return Err(self.error(&format!(
+                "Cannot import '{}', another module is already named '{}'",
+                path, name
+            )));
+        }
+
+        let source = std::fs::read_to_string(&file)
+            .map_err(|err| self.io_error(&format!("Cannot import '{}': {}", path, err)))?;
+
+        // Shared by every span of the module, so errors can still show where they happened
+        let file_name: Arc<str> = file.to_string_lossy().into();
+        let tokens = Lexer::new(source).in_file(file_name).scan_tokens()?;
+        let ast = Parser::new(tokens).parse()?;
+
+        let procedures = ast
+            .iter()
+            .filter_map(|decl| match decl {
+                Declaration::Procedure(name, _, _) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+
+        self.op_cost(self.vm.economy.costs.import);
+
+        let directory = std::mem::replace(
+            &mut self.directory,
+            file.parent().map(Path::to_path_buf).unwrap_or_default(),
+        );
+        let module = self.module.replace(Module {
+            name: name.clone(),
+            procedures,
+        });
+        self.importing.push(canonical.clone());
+
+        let result = ast.into_iter().try_for_each(|decl| self.declaration(decl));
+
+        self.importing.pop();
+        self.module = module;
+        self.directory = directory;
+        result?;
+
+        self.modules.push((canonical, name));
+
+        Ok(())
+    }
+
+    // Prefixes procedures of the module being compiled with its name
+    fn qualify(&self, name: String) -> String {
+        match &self.module {
+            Some(module) if module.procedures.contains(&name) => {
+                format!("{}.{}", module.name, name)
+            }
+            _ => name,
+        }
+    }
+
+    fn procedure(
+        &mut self,
+        name: String,
+        parameters: Vec<String>,
+        code: Vec<Statement>,
+    ) -> Result<(), MonopolangError> {
+        // Procedures live in the same code segment as the main program,
+        // so jump over the body when execution reaches the declaration
+        let skip_body = self.write_op(OpCode::Jump(0));
+
+        let address = self.vm.code.len();
+        let name = self.qualify(name);
+        self.vm.write_procedure(name, address, parameters.len());
+
+        // Arguments are pushed by the caller, so parameters occupy the first stack slots of the frame
+        // and locals declared with let the slots after them, returning discards them all
+        self.locals = parameters;
+        self.in_procedure = true;
+        self.scopes.push(self.locals.len());
+
+        for stmt in code {
+            self.statement(stmt)?;
+        }
+
+        self.scopes.pop();
+        self.locals.clear();
+        self.in_procedure = false;
+
+        // Procedures that fall off the end return void
+        self.expression(Expression::Void)?;
+        self.write_op(OpCode::Return);
+
+        let current_idx = self.vm.code.len();
+        self.vm.code[skip_body] = OpCode::Jump(current_idx);
+
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: Statement) -> Result<(), MonopolangError> {
+        let enclosing_span = self.span.clone();
+        self.span = stmt.span;
+
+        match stmt.kind {
+            StatementKind::Print(expr) => {
+                self.op_cost(self.vm.economy.costs.print);
+                self.expression(expr)?;
+                self.write_op(OpCode::Print);
+            }
+            StatementKind::Expression(expr) => {
+                self.expression(expr)?;
+                self.write_op(OpCode::Pop);
+            }
+            StatementKind::VariableAssignment(name, expr) => {
+                self.op_cost(self.vm.economy.costs.assignment);
+                self.expression(expr)?;
+                self.set_variable(name)?;
+            }
+            StatementKind::IndexAssignment(list, index, value) => {
+                self.op_cost(self.vm.economy.costs.assignment);
+                self.expression(list)?;
+                self.expression(index)?;
+                self.expression(value)?;
+                self.write_op(OpCode::SetIndex);
+            }
+            StatementKind::Let(name, value) => {
+                // The value stays on the stack as the local's slot, so there is nothing to store
+                let scope = match self.scopes.last() {
+                    Some(scope) => *scope,
+                    None => return Err(self.error(
+                        "'let' can only be used inside a block or procedure, use 'set' for globals",
+                    )),
+                };
+                if self.locals[scope..].contains(&name) {
+                    return Err(
+                        self.error(&format!("'{}' is already declared in this block", name))
+                    );
+                }
+
+                self.op_cost(self.vm.economy.costs.assignment);
+                self.expression(value)?;
+                self.locals.push(name);
+            }
+            StatementKind::Block(stmts) => {
+                self.scopes.push(self.locals.len());
+
                 for stmt in stmts {
-                    self.statement(stmt);
+                    self.statement(stmt)?;
+                }
+
+                // Locals declared in the block go out of scope with it
+                let scope = self.scopes.pop().unwrap();
+                for _ in scope..self.locals.len() {
+                    self.write_op(OpCode::Pop);
                 }
+                self.locals.truncate(scope);
             }
-            Statement::ProcedureCall(name) => {
-                self.op_cost(5);
-                self.vm.write_op(OpCode::ProcedureCall(name));
+            StatementKind::ProcedureCall(name, arguments) => {
+                self.call(name, arguments)?;
+                // Called as a statement, so the return value is discarded
+                self.write_op(OpCode::Pop);
             }
-            Statement::If(cond, then_branch, else_branch) => {
-                self.op_cost(3);
-                self.expression(cond);
-                let jump_forward = self.vm.write_op(OpCode::JumpIfFalse(0));
-                self.statement(*then_branch);
-                // If there is an else branch, we need to jump over it
-                // Patch jump_forward to jump to the end of the then branch
-                let current_idx = self.vm.code.len();
-                self.vm.code[jump_forward] = OpCode::JumpIfFalse(current_idx);
-                // If there is an else branch, we need to jump over it
-                if let Some(else_branch) = else_branch {
-                    // +1 to the jump offset to skip the jump over the else branch
-                    match self.vm.code[jump_forward] {
-                        OpCode::JumpIfFalse(ref mut offset) => *offset += 1,
-                        _ => unreachable!(),
+            StatementKind::Return(expr) => {
+                if !self.in_procedure {
+                    return Err(self.error("Cannot return from outside a procedure"));
+                }
+
+                self.expression(expr)?;
+                self.write_op(OpCode::Return);
+            }
+            StatementKind::If(branches, else_branch) => {
+                // Conditions are tried in order, each one is only charged for if it gets evaluated
+                let mut exits = Vec::new();
+                let branch_count = branches.len();
+
+                for (i, (cond, branch)) in branches.into_iter().enumerate() {
+                    self.op_cost(self.vm.economy.costs.if_statement);
+                    self.expression(cond)?;
+                    let jump_forward = self.write_op(OpCode::JumpIfFalse(0));
+                    self.statement(branch)?;
+
+                    // Taken branches jump over the rest of the chain
+                    if i + 1 < branch_count || else_branch.is_some() {
+                        exits.push(self.write_op(OpCode::Jump(0)));
                     }
 
-                    let skip_else = self.vm.write_op(OpCode::Jump(0));
-                    self.statement(*else_branch);
                     let current_idx = self.vm.code.len();
-                    self.vm.code[skip_else] = OpCode::Jump(current_idx);
+                    self.vm.code[jump_forward] = OpCode::JumpIfFalse(current_idx);
+                }
+
+                if let Some(else_branch) = else_branch {
+                    self.statement(*else_branch)?;
+                }
+
+                let current_idx = self.vm.code.len();
+                for exit in exits {
+                    self.vm.code[exit] = OpCode::Jump(current_idx);
                 }
             }
-            Statement::While(cond, body) => {
-                self.op_cost(5);
+            StatementKind::While(cond, body) => {
+                self.op_cost(self.vm.economy.costs.while_loop);
                 let loop_start = self.vm.code.len();
-                self.expression(cond);
-                let jump_forward = self.vm.write_op(OpCode::JumpIfFalse(0));
-                self.statement(*body);
-                self.vm.write_op(OpCode::Jump(loop_start));
+                self.expression(cond)?;
+                let jump_forward = self.write_op(OpCode::JumpIfFalse(0));
+                self.begin_loop();
+                self.statement(*body)?;
+                self.write_op(OpCode::Jump(loop_start));
                 let current_idx = self.vm.code.len();
                 self.vm.code[jump_forward] = OpCode::JumpIfFalse(current_idx);
+                self.end_loop(loop_start, current_idx);
             }
-            Statement::Range(variable, start, end, step, body) => {
-                // Convert the range to a while loop
-                // No op_cost here because it transforms to a while loop
-                self.expression(start);
-                self.vm.write_op(OpCode::SetGlobal(variable.clone()));
-
-                self.statement(Statement::While(
-                    Expression::Binary(
-                        BinaryOperator::Less,
-                        Box::new(Expression::Variable(variable.clone())),
-                        Box::new(end),
-                    ),
-                    Box::new(Statement::Block(vec![
-                        *body.clone(),
-                        Statement::VariableAssignment(
-                            variable.clone(),
-                            Expression::Binary(
-                                BinaryOperator::Add,
-                                Box::new(Expression::Variable(variable)),
-                                Box::new(step),
-                            ),
+            StatementKind::Range(variable, start, end, step, body) => {
+                // Ranges are priced like while loops, with every step charged as an assignment
+                self.op_cost(self.vm.economy.costs.while_loop);
+                self.expression(start)?;
+                self.set_variable(variable.clone())?;
+
+                let loop_start = self.vm.code.len();
+                self.expression(Expression::Binary(
+                    BinaryOperator::Less,
+                    Box::new(Expression::Variable(variable.clone())),
+                    Box::new(end),
+                ))?;
+                let jump_forward = self.write_op(OpCode::JumpIfFalse(0));
+                self.begin_loop();
+                self.statement(*body)?;
+
+                // continue jumps here, so the step still runs
+                let step_start = self.vm.code.len();
+                self.statement(Statement::new(
+                    StatementKind::VariableAssignment(
+                        variable.clone(),
+                        Expression::Binary(
+                            BinaryOperator::Add,
+                            Box::new(Expression::Variable(variable)),
+                            Box::new(step),
                         ),
-                    ])),
-                ));
+                    ),
+                    self.span.clone(),
+                ))?;
+                self.write_op(OpCode::Jump(loop_start));
+
+                let current_idx = self.vm.code.len();
+                self.vm.code[jump_forward] = OpCode::JumpIfFalse(current_idx);
+                self.end_loop(step_start, current_idx);
+            }
+            StatementKind::ForEach(variable, collection, body) => {
+                self.op_cost(self.vm.economy.costs.for_loop);
+
+                // Inside a procedure or block the loop variable is a local too, so a recursive call
+                // made in the body can't overwrite it. Its slot goes below the loop state, which
+                // ForNext expects on top of the stack, and is only named once the collection is evaluated
+                let item = if self.in_procedure || !self.scopes.is_empty() {
+                    self.expression(Expression::Void)?;
+                    self.locals.push(" item".to_string());
+                    Some(self.locals.len() - 1)
+                } else {
+                    None
+                };
+
+                // The items and the position in them stay on the stack while looping,
+                // as unnamed locals so slots of later locals stay correct
+                self.expression(collection)?;
+                self.write_op(OpCode::Iterate);
+                self.locals.push(" items".to_string());
+                self.expression(Expression::Number(0.0))?;
+                self.locals.push(" index".to_string());
+
+                if let Some(slot) = item {
+                    self.locals[slot] = variable.clone();
+                }
+
+                let loop_start = self.write_op(OpCode::ForNext(0));
+                self.op_cost(self.vm.economy.costs.assignment);
+                self.set_variable(variable)?;
+                self.begin_loop();
+                self.statement(*body)?;
+                self.write_op(OpCode::Jump(loop_start));
+
+                // break jumps here, to the cleanup of the loop state
+                let current_idx = self.vm.code.len();
+                self.vm.code[loop_start] = OpCode::ForNext(current_idx);
+                self.end_loop(loop_start, current_idx);
+
+                let state = if item.is_some() { 3 } else { 2 };
+                for _ in 0..state {
+                    self.write_op(OpCode::Pop);
+                }
+                self.locals.truncate(self.locals.len() - state);
+            }
+            StatementKind::Break | StatementKind::Continue => {
+                let is_break = matches!(stmt.kind, StatementKind::Break);
+
+                let locals = match self.loops.last() {
+                    Some(current) => current.locals,
+                    None if is_break => return Err(self.error("Cannot break outside of a loop")),
+                    None => return Err(self.error("Cannot continue outside of a loop")),
+                };
+
+                // Leave behind the values of locals declared inside the loop
+                for _ in locals..self.locals.len() {
+                    self.write_op(OpCode::Pop);
+                }
+
+                let jump = self.write_op(OpCode::Jump(0));
+                let current = self.loops.last_mut().unwrap();
+
+                if is_break {
+                    current.breaks.push(jump);
+                } else {
+                    current.continues.push(jump);
+                }
+            }
+            StatementKind::Buy(name, amount) => {
+                self.trade(name, amount, OpCode::Buy)?;
             }
-            // TODO: Economic statements
-            Statement::Buy(name, amount) => {
-                self.expression(amount);
-                self.expression(name);
-                self.vm.write_op(OpCode::Buy);
+            StatementKind::Sell(name, amount) => {
+                self.trade(name, amount, OpCode::Sell)?;
             }
-            Statement::Sell(_, _) => {}
-            Statement::Loan(expr) => {
-                self.expression(expr);
-                self.vm.write_op(OpCode::Loan);
+            StatementKind::Loan(expr) => {
+                self.expression(expr)?;
+                self.write_op(OpCode::Loan);
             }
-            Statement::Pay(expr) => {
-                self.expression(expr);
-                self.vm.write_op(OpCode::Repay);
+            StatementKind::Pay(expr) => {
+                self.expression(expr)?;
+                self.write_op(OpCode::Repay);
             }
-            Statement::Gamble(expr) => {
-                self.expression(expr);
-                self.vm.write_op(OpCode::Gamble);
+            StatementKind::Gamble(expr) => {
+                self.expression(expr)?;
+                self.write_op(OpCode::Gamble);
             }
-            Statement::Work => {
-                self.vm.write_op(OpCode::Work);
+            StatementKind::Work => {
+                self.write_op(OpCode::Work);
             }
         }
+
+        self.span = enclosing_span;
+
+        Ok(())
     }
 
-    fn expression(&mut self, expr: Expression) {
+    fn expression(&mut self, expr: Expression) -> Result<(), MonopolangError> {
         match expr {
             Expression::Number(n) => {
                 let idx = self.write_constant(Value::from_number(n));
-                self.vm.write_op(OpCode::Constant(idx));
+                self.write_op(OpCode::Constant(idx));
             }
             Expression::String(s) => {
                 let idx = self.write_constant(Value::from_string(s.as_str()));
-                self.vm.write_op(OpCode::Constant(idx));
+                self.write_op(OpCode::Constant(idx));
             }
             Expression::Boolean(b) => {
                 let idx = self.write_constant(Value::from_boolean(b));
-                self.vm.write_op(OpCode::Constant(idx));
-            }
-            Expression::Variable(name) => {
-                self.vm.write_op(OpCode::GetGlobal(name));
+                self.write_op(OpCode::Constant(idx));
             }
+            Expression::Variable(name) => match self.resolve_local(&name) {
+                Some(slot) => {
+                    self.read_cost();
+                    self.write_op(OpCode::GetLocal(slot));
+                }
+                // Constants known at compile time are used directly
+                None => match self.constants.get(&name) {
+                    Some(Some(value)) => {
+                        let idx = self.write_constant(value.clone());
+                        self.write_op(OpCode::Constant(idx));
+                    }
+                    Some(None) => {
+                        self.write_op(OpCode::GetGlobal(name));
+                    }
+                    None => {
+                        self.read_cost();
+                        self.write_op(OpCode::GetGlobal(name));
+                    }
+                },
+            },
             Expression::ReadonlyVariable(name) => {
-                self.vm.write_op(OpCode::GetGlobal(name));
+                self.write_op(OpCode::GetGlobal(name));
             }
             Expression::StockPrice(name) => {
-                self.vm.write_op(OpCode::GetStockPrice(name));
+                self.write_op(OpCode::GetStockPrice(name));
+            }
+            Expression::Call(name, arguments) => {
+                self.call(name, arguments)?;
+            }
+            Expression::List(elements) => {
+                // Every element stored costs money
+                self.op_cost(self.vm.economy.costs.element * elements.len() as f64);
+
+                let length = elements.len();
+                for element in elements {
+                    self.expression(element)?;
+                }
+
+                self.write_op(OpCode::BuildList(length));
+            }
+            Expression::Map(entries) => {
+                // Entries cost the same to store as list elements
+                self.op_cost(self.vm.economy.costs.element * entries.len() as f64);
+
+                let length = entries.len();
+                for (key, value) in entries {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+
+                self.write_op(OpCode::BuildMap(length));
+            }
+            Expression::Index(list, index) => {
+                self.expression(*list)?;
+                self.expression(*index)?;
+                self.write_op(OpCode::GetIndex);
             }
             Expression::Unary(op, expr) => {
-                self.expression(*expr);
+                self.expression(*expr)?;
                 match op {
-                    UnaryOperator::Negate => self.vm.write_op(OpCode::Negate),
-                    UnaryOperator::Not => self.vm.write_op(OpCode::Not),
+                    UnaryOperator::Negate => self.write_op(OpCode::Negate),
+                    UnaryOperator::Not => self.write_op(OpCode::Not),
                 };
             }
             Expression::Binary(op, left, right) => {
-                self.expression(*left);
-                self.expression(*right);
+                let price = self.operator_price(op);
+                if price > 0.0 {
+                    self.op_cost(price);
+                }
+
+                self.expression(*left)?;
+                self.expression(*right)?;
                 match op {
-                    BinaryOperator::Add => self.vm.write_op(OpCode::Add),
-                    BinaryOperator::Subtract => self.vm.write_op(OpCode::Subtract),
-                    BinaryOperator::Multiply => self.vm.write_op(OpCode::Multiply),
-                    BinaryOperator::Divide => self.vm.write_op(OpCode::Divide),
-                    BinaryOperator::Equal => self.vm.write_op(OpCode::Equal),
-                    BinaryOperator::NotEqual => self.vm.write_op(OpCode::NotEqual),
-                    BinaryOperator::Less => self.vm.write_op(OpCode::Less),
-                    BinaryOperator::LessEqual => self.vm.write_op(OpCode::LessEqual),
-                    BinaryOperator::Greater => self.vm.write_op(OpCode::Greater),
-                    BinaryOperator::GreaterEqual => self.vm.write_op(OpCode::GreaterEqual),
+                    BinaryOperator::Add => self.write_op(OpCode::Add),
+                    BinaryOperator::Subtract => self.write_op(OpCode::Subtract),
+                    BinaryOperator::Multiply => self.write_op(OpCode::Multiply),
+                    BinaryOperator::Divide => self.write_op(OpCode::Divide),
+                    BinaryOperator::Modulo => self.write_op(OpCode::Modulo),
+                    BinaryOperator::IntegerDivide => self.write_op(OpCode::IntegerDivide),
+                    BinaryOperator::Power => self.write_op(OpCode::Power),
+                    BinaryOperator::BitAnd => self.write_op(OpCode::BitAnd),
+                    BinaryOperator::BitOr => self.write_op(OpCode::BitOr),
+                    BinaryOperator::BitXor => self.write_op(OpCode::BitXor),
+                    BinaryOperator::ShiftLeft => self.write_op(OpCode::ShiftLeft),
+                    BinaryOperator::ShiftRight => self.write_op(OpCode::ShiftRight),
+                    BinaryOperator::Equal => self.write_op(OpCode::Equal),
+                    BinaryOperator::NotEqual => self.write_op(OpCode::NotEqual),
+                    BinaryOperator::Less => self.write_op(OpCode::Less),
+                    BinaryOperator::LessEqual => self.write_op(OpCode::LessEqual),
+                    BinaryOperator::Greater => self.write_op(OpCode::Greater),
+                    BinaryOperator::GreaterEqual => self.write_op(OpCode::GreaterEqual),
                 };
             }
             Expression::Logical(op, left, right) => {
-                self.expression(*left);
-                self.expression(*right);
-                match op {
-                    LogicalOperator::And => self.vm.write_op(OpCode::And),
-                    LogicalOperator::Or => self.vm.write_op(OpCode::Or),
+                // The right operand is skipped once the left one decides the result,
+                // which is then the value of the whole expression
+                self.expression(*left)?;
+                let jump_forward = match op {
+                    LogicalOperator::And => self.write_op(OpCode::JumpIfFalseKeep(0)),
+                    LogicalOperator::Or => self.write_op(OpCode::JumpIfTrueKeep(0)),
                 };
+                self.expression(*right)?;
+
+                let current_idx = self.vm.code.len();
+                self.vm.code[jump_forward] = match op {
+                    LogicalOperator::And => OpCode::JumpIfFalseKeep(current_idx),
+                    LogicalOperator::Or => OpCode::JumpIfTrueKeep(current_idx),
+                };
+            }
+            Expression::Input(number) => {
+                // Billed by the VM once it knows how many bytes were read
+                self.write_op(if number {
+                    OpCode::InputNumber
+                } else {
+                    OpCode::Input
+                });
             }
-            Expression::Void => {} // TODO: Implement void expression
+            Expression::Interpolation(parts) => {
+                // Parts are joined in one go, so there is no operator to pay for between them
+                let length = parts.len();
+                for part in parts {
+                    match part {
+                        InterpolationPart::Literal(text) => {
+                            let idx = self.write_constant(Value::String(text));
+                            self.write_op(OpCode::Constant(idx));
+                        }
+                        InterpolationPart::Expression(expression, decimals) => {
+                            self.expression(expression)?;
+                            if let Some(decimals) = decimals {
+                                self.write_op(OpCode::FormatDecimals(decimals));
+                            }
+                        }
+                    }
+                }
+
+                self.write_op(OpCode::Join(length));
+            }
+            Expression::Void => {
+                let idx = self.write_constant(Value::Void);
+                self.write_op(OpCode::Constant(idx));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn call(&mut self, name: String, arguments: Vec<Expression>) -> Result<(), MonopolangError> {
+        if let Some(builtin) = Builtin::from_name(&name) {
+            return self.builtin(builtin, arguments);
+        }
+        let name = self.qualify(name);
+
+        // Calling has a flat price, plus a price for every argument passed
+        let costs = &self.vm.economy.costs;
+        self.op_cost(costs.procedure_call + costs.argument * arguments.len() as f64);
+
+        let arity = arguments.len();
+        for argument in arguments {
+            self.expression(argument)?;
         }
+
+        self.write_op(OpCode::Call(name, arity));
+
+        Ok(())
+    }
+
+    // Builtins are checked here rather than at runtime, since their arity is known up front
+    fn builtin(
+        &mut self,
+        builtin: Builtin,
+        arguments: Vec<Expression>,
+    ) -> Result<(), MonopolangError> {
+        if arguments.len() != builtin.arity() {
+            return Err(self.error(&format!(
+                "{} expects {} arguments but got {}",
+                builtin.name(),
+                builtin.arity(),
+                arguments.len()
+            )));
+        }
+
+        self.op_cost(self.vm.economy.costs.builtin_call);
+
+        let arity = arguments.len();
+        for argument in arguments {
+            self.expression(argument)?;
+        }
+
+        self.write_op(OpCode::Builtin(builtin, arity));
+
+        Ok(())
+    }
+
+    // Buying and selling are compiled the same way, so they cost the same and validate the same
+    fn trade(
+        &mut self,
+        name: Expression,
+        amount: Expression,
+        op: OpCode,
+    ) -> Result<(), MonopolangError> {
+        // Every trade pays a broker fee
+        self.op_cost(self.vm.economy.costs.trade);
+        self.expression(amount)?;
+        self.expression(name)?;
+        self.write_op(op);
+
+        Ok(())
+    }
+
+    fn begin_loop(&mut self) {
+        self.loops.push(Loop {
+            breaks: Vec::new(),
+            continues: Vec::new(),
+            locals: self.locals.len(),
+        });
+    }
+
+    // Points the loop's continues at continue_target and its breaks at exit
+    fn end_loop(&mut self, continue_target: usize, exit: usize) {
+        let current = self.loops.pop().unwrap();
+
+        for jump in current.continues {
+            self.vm.code[jump] = OpCode::Jump(continue_target);
+        }
+
+        for jump in current.breaks {
+            self.vm.code[jump] = OpCode::Jump(exit);
+        }
+    }
+
+    fn operator_price(&self, op: BinaryOperator) -> f64 {
+        let operators = &self.vm.economy.operators;
+
+        match op {
+            BinaryOperator::Add => operators.add,
+            BinaryOperator::Subtract => operators.subtract,
+            BinaryOperator::Multiply => operators.multiply,
+            BinaryOperator::Divide => operators.divide,
+            BinaryOperator::Modulo => operators.modulo,
+            BinaryOperator::IntegerDivide => operators.integer_division,
+            BinaryOperator::Power => operators.power,
+            BinaryOperator::BitAnd
+            | BinaryOperator::BitOr
+            | BinaryOperator::BitXor
+            | BinaryOperator::ShiftLeft
+            | BinaryOperator::ShiftRight => operators.bitwise,
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual
+            | BinaryOperator::Less
+            | BinaryOperator::LessEqual => operators.comparison,
+        }
+    }
+
+    fn set_variable(&mut self, name: String) -> Result<(), MonopolangError> {
+        match self.resolve_local(&name) {
+            Some(slot) => self.write_op(OpCode::SetLocal(slot)),
+            None if self.constants.contains_key(&name) => {
+                return Err(self.error(&format!("Cannot assign to constant '{}'", name)))
+            }
+            None => self.write_op(OpCode::SetGlobal(name)),
+        };
+
+        Ok(())
+    }
+
+    // Reading a variable is free unless the economy prices it
+    fn read_cost(&mut self) {
+        let price = self.vm.economy.costs.read;
+        if price > 0.0 {
+            self.op_cost(price);
+        }
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.locals.iter().rposition(|local| local == name)
     }
 
     pub fn write_constant(&mut self, value: Value) -> usize {
-        // Write to parent if exists
-        if let Some(parent) = &mut self.parent {
-            return parent.write_constant(value);
-        } else {
-            self.vm.write_constant(value)
+        self.vm.write_constant(value)
+    }
+
+    pub fn op_cost(&mut self, cost: f64) {
+        self.write_op(OpCode::Cost(cost));
+    }
+
+    fn write_op(&mut self, op: OpCode) -> usize {
+        self.vm.write_op(op, self.span.clone())
+    }
+
+    fn error(&self, message: &str) -> MonopolangError {
+        MonopolangError::Compile {
+            message: message.to_string(),
+            span: self.span.clone(),
         }
     }
 
-    pub fn op_cost(&mut self, cost: i32) {
-        self.vm.write_op(OpCode::Cost(cost as f64));
+    fn io_error(&self, message: &str) -> MonopolangError {
+        MonopolangError::Io {
+            message: message.to_string(),
+            span: self.span.clone(),
+        }
     }
 }
+
+// File name of a module, for error messages
+fn display_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
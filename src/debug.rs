@@ -10,56 +10,80 @@ pub fn traverse_print_decl(decl: &ast::Declaration, indent: usize) {
             println!("{}Statement:", " ".repeat(indent));
             traverse_print_stmt(stmt, indent + 1);
         }
-        ast::Declaration::Procedure(name, code) => {
+        ast::Declaration::Procedure(name, parameters, code) => {
             println!("{}Procedure: {}", " ".repeat(indent), name);
+            println!("{}Parameters: {:?}", " ".repeat(indent), parameters);
             println!("{}Code:", " ".repeat(indent));
             for stmt in code {
                 traverse_print_stmt(stmt, indent + 1);
             }
         }
+        ast::Declaration::Import(path, _) => {
+            println!("{}Import: {}", " ".repeat(indent), path);
+        }
+        ast::Declaration::Constant(name, value, _) => {
+            println!("{}Constant: {}", " ".repeat(indent), name);
+            traverse_print_expr(value, indent + 1);
+        }
     }
 }
 
 pub fn traverse_print_stmt(stmt: &ast::Statement, indent: usize) {
-    match stmt {
-        ast::Statement::VariableAssignment(name, initializer) => {
+    match &stmt.kind {
+        ast::StatementKind::VariableAssignment(name, initializer) => {
             println!("{}Variable: {}", " ".repeat(indent), name);
             println!("{}Value:", " ".repeat(indent));
             traverse_print_expr(initializer, indent + 1);
         }
-        ast::Statement::Expression(expr) => {
+        ast::StatementKind::IndexAssignment(list, index, value) => {
+            println!("{}IndexAssignment:", " ".repeat(indent));
+            println!("{}List:", " ".repeat(indent + 1));
+            traverse_print_expr(list, indent + 2);
+            println!("{}Index:", " ".repeat(indent + 1));
+            traverse_print_expr(index, indent + 2);
+            println!("{}Value:", " ".repeat(indent + 1));
+            traverse_print_expr(value, indent + 2);
+        }
+        ast::StatementKind::Expression(expr) => {
             println!("{}Expression:", " ".repeat(indent));
             traverse_print_expr(expr, indent + 1);
         }
-        ast::Statement::Print(expr) => {
+        ast::StatementKind::Print(expr) => {
             println!("{}Print:", " ".repeat(indent));
             traverse_print_expr(expr, indent + 1);
         }
-        ast::Statement::Block(stmts) => {
+        ast::StatementKind::Let(name, initializer) => {
+            println!("{}Let: {}", " ".repeat(indent), name);
+            println!("{}Value:", " ".repeat(indent));
+            traverse_print_expr(initializer, indent + 1);
+        }
+        ast::StatementKind::Block(stmts) => {
             println!("{}Block:", " ".repeat(indent));
             for stmt in stmts {
                 traverse_print_stmt(stmt, indent + 1);
             }
         }
-        ast::Statement::If(condition, then_branch, else_branch) => {
+        ast::StatementKind::If(branches, else_branch) => {
             println!("{}If:", " ".repeat(indent));
-            println!("{}Condition:", " ".repeat(indent + 1));
-            traverse_print_expr(condition, indent + 2);
-            println!("{}Then:", " ".repeat(indent + 1));
-            traverse_print_stmt(then_branch, indent + 2);
+            for (condition, branch) in branches {
+                println!("{}Condition:", " ".repeat(indent + 1));
+                traverse_print_expr(condition, indent + 2);
+                println!("{}Then:", " ".repeat(indent + 1));
+                traverse_print_stmt(branch, indent + 2);
+            }
             if let Some(else_branch) = else_branch {
                 println!("{}Else:", " ".repeat(indent + 1));
                 traverse_print_stmt(else_branch, indent + 2);
             }
         }
-        ast::Statement::While(condition, body) => {
+        ast::StatementKind::While(condition, body) => {
             println!("{}While:", " ".repeat(indent));
             println!("{}Condition:", " ".repeat(indent + 1));
             traverse_print_expr(condition, indent + 2);
             println!("{}Body:", " ".repeat(indent + 1));
             traverse_print_stmt(body, indent + 2);
         }
-        ast::Statement::Range(name, start, end, step, body) => {
+        ast::StatementKind::Range(name, start, end, step, body) => {
             println!("{}Range: {}", " ".repeat(indent), name);
             println!("{}Start:", " ".repeat(indent + 1));
             traverse_print_expr(start, indent + 2);
@@ -70,36 +94,56 @@ pub fn traverse_print_stmt(stmt: &ast::Statement, indent: usize) {
             println!("{}Body:", " ".repeat(indent + 1));
             traverse_print_stmt(body, indent + 2);
         }
-        ast::Statement::ProcedureCall(name) => {
+        ast::StatementKind::ForEach(name, collection, body) => {
+            println!("{}ForEach: {}", " ".repeat(indent), name);
+            println!("{}Collection:", " ".repeat(indent + 1));
+            traverse_print_expr(collection, indent + 2);
+            println!("{}Body:", " ".repeat(indent + 1));
+            traverse_print_stmt(body, indent + 2);
+        }
+        ast::StatementKind::ProcedureCall(name, arguments) => {
             println!("{}ProcedureCall: {}", " ".repeat(indent), name);
+            for argument in arguments {
+                traverse_print_expr(argument, indent + 1);
+            }
         }
-        ast::Statement::Gamble(expr) => {
+        ast::StatementKind::Return(value) => {
+            println!("{}Return:", " ".repeat(indent));
+            traverse_print_expr(value, indent + 1);
+        }
+        ast::StatementKind::Gamble(expr) => {
             println!("{}Gamble:", " ".repeat(indent));
             traverse_print_expr(expr, indent + 1);
         }
-        ast::Statement::Buy(stock, amount) => {
+        ast::StatementKind::Buy(stock, amount) => {
             println!("{}Buy:", " ".repeat(indent));
             println!("{}Stock:", " ".repeat(indent + 1));
             traverse_print_expr(stock, indent + 2);
             println!("{}Amount:", " ".repeat(indent + 1));
             traverse_print_expr(amount, indent + 2);
         }
-        ast::Statement::Sell(stock, amount) => {
+        ast::StatementKind::Sell(stock, amount) => {
             println!("{}Sell:", " ".repeat(indent));
             println!("{}Stock:", " ".repeat(indent + 1));
             traverse_print_expr(stock, indent + 2);
             println!("{}Amount:", " ".repeat(indent + 1));
             traverse_print_expr(amount, indent + 2);
         }
-        ast::Statement::Loan(amount) => {
+        ast::StatementKind::Loan(amount) => {
             println!("{}Loan:", " ".repeat(indent));
             traverse_print_expr(amount, indent + 1);
         }
-        ast::Statement::Pay(amount) => {
+        ast::StatementKind::Pay(amount) => {
             println!("{}Pay:", " ".repeat(indent));
             traverse_print_expr(amount, indent + 1);
         }
-        ast::Statement::Work => {
+        ast::StatementKind::Break => {
+            println!("{}Break", " ".repeat(indent));
+        }
+        ast::StatementKind::Continue => {
+            println!("{}Continue", " ".repeat(indent));
+        }
+        ast::StatementKind::Work => {
             println!("{}Work", " ".repeat(indent));
         }
     }
@@ -128,6 +172,30 @@ pub fn traverse_print_expr(expr: &ast::Expression, indent: usize) {
         ast::Expression::StockPrice(name) => {
             println!("{}StockPrice: {}", " ".repeat(indent), name);
         }
+        ast::Expression::Call(name, arguments) => {
+            println!("{}Call: {}", " ".repeat(indent), name);
+            for argument in arguments {
+                traverse_print_expr(argument, indent + 1);
+            }
+        }
+        ast::Expression::List(elements) => {
+            println!("{}List:", " ".repeat(indent));
+            for element in elements {
+                traverse_print_expr(element, indent + 1);
+            }
+        }
+        ast::Expression::Map(entries) => {
+            println!("{}Map:", " ".repeat(indent));
+            for (key, value) in entries {
+                traverse_print_expr(key, indent + 1);
+                traverse_print_expr(value, indent + 2);
+            }
+        }
+        ast::Expression::Index(list, index) => {
+            println!("{}Index:", " ".repeat(indent));
+            traverse_print_expr(list, indent + 1);
+            traverse_print_expr(index, indent + 1);
+        }
         ast::Expression::Unary(operator, right) => {
             println!("{}Unary: {:?}", " ".repeat(indent), operator);
             traverse_print_expr(right, indent + 1);
@@ -142,5 +210,22 @@ pub fn traverse_print_expr(expr: &ast::Expression, indent: usize) {
             traverse_print_expr(left, indent + 1);
             traverse_print_expr(right, indent + 1);
         }
+        ast::Expression::Input(number) => {
+            println!("{}Input: number {}", " ".repeat(indent), number);
+        }
+        ast::Expression::Interpolation(parts) => {
+            println!("{}Interpolation", " ".repeat(indent));
+            for part in parts {
+                match part {
+                    ast::InterpolationPart::Literal(text) => {
+                        println!("{}Literal: {:?}", " ".repeat(indent + 1), text)
+                    }
+                    ast::InterpolationPart::Expression(expression, decimals) => {
+                        println!("{}Expression: {:?}", " ".repeat(indent + 1), decimals);
+                        traverse_print_expr(expression, indent + 2);
+                    }
+                }
+            }
+        }
     }
 }
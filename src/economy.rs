@@ -0,0 +1,409 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+// Every tunable number of the economy model
+// Profiles loaded from files only need to list the values they change, the rest use the defaults
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Economy {
+    pub starting_balance: f64,
+    pub costs: Costs,
+    pub operators: Operators,
+    pub debt_collection: DebtCollection,
+    pub work: Work,
+    pub stocks: Stocks,
+    pub loans: Loans,
+    pub gambling: Gambling,
+    pub inflation: Inflation,
+    pub taxes: Taxes,
+}
+
+// Price of each language feature, charged every time it runs
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Costs {
+    pub print: f64,
+    pub assignment: f64,
+    pub if_statement: f64,
+    pub while_loop: f64,
+    pub for_loop: f64,
+    pub procedure_call: f64,
+    pub argument: f64,     // Per argument passed to a procedure
+    pub trade: f64,        // Broker fee for buy and sell
+    pub builtin_call: f64, // Calling a builtin function like len or push
+    pub element: f64,      // Storing an element in a list, charged for literals and push
+    pub character: f64,    // Per character of the strings passed to string functions
+    pub input: f64,        // Per byte read by input and input_number
+    pub file: f64,         // Per started kilobyte read or written by the file functions
+    pub import: f64,       // One-time licensing fee for every module imported
+    pub constant: f64,     // One-time fee for defining a constant, reading one is always free
+    pub read: f64,         // Per read of a variable, constants are always free to read
+}
+
+// Price of each binary operator, heavier math costs more
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Operators {
+    pub add: f64,
+    pub subtract: f64,
+    pub multiply: f64,
+    pub divide: f64,
+    pub modulo: f64,
+    pub integer_division: f64,
+    pub power: f64,
+    pub bitwise: f64,    // &, |, ^, << and >>
+    pub comparison: f64, // =, !=, <, <=, > and >=
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebtCollection {
+    pub interval: u32, // Operations between forced collections
+    pub rate: f64,     // Fraction of the debt collected each time
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Work {
+    pub cooldown: u32,    // Operations before work is allowed again
+    pub pay_rate: f64,    // Fraction of the balance earned per work
+    pub minimum_pay: f64, // Pay when the balance is too small for pay_rate to be worth it
+    pub duration_ms: u64, // Time spent working, the program is paused meanwhile
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stocks {
+    pub initial_price: f64, // Newly listed stocks get a random price up to this
+    pub tick_interval: u32, // Operations between price updates
+    pub volatility: f64,    // Maximum random price change per update
+    pub drift: f64,         // Fixed price change per update, negative for a falling market
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Loans {
+    pub max_multiplier: f64, // Loans are capped at (balance - debt) * max_multiplier
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Gambling {
+    pub win_chance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Inflation {
+    pub interval: u32, // Operations between price increases
+    pub rate: f64,     // Costs grow by this fraction every interval
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Taxes {
+    pub interval: u32, // Operations in a tax period
+    pub rate: f64,     // Fraction of the period's gains paid as tax
+}
+
+pub const PRESETS: [&str; 4] = ["default", "easy", "recession", "hyperinflation"];
+
+impl Default for Economy {
+    fn default() -> Self {
+        Economy {
+            starting_balance: 250.0,
+            costs: Costs::default(),
+            operators: Operators::default(),
+            debt_collection: DebtCollection::default(),
+            work: Work::default(),
+            stocks: Stocks::default(),
+            loans: Loans::default(),
+            gambling: Gambling::default(),
+            inflation: Inflation::default(),
+            taxes: Taxes::default(),
+        }
+    }
+}
+
+impl Default for Costs {
+    fn default() -> Self {
+        Costs {
+            print: 1.0,
+            assignment: 2.0,
+            if_statement: 3.0,
+            while_loop: 5.0,
+            for_loop: 5.0,
+            procedure_call: 5.0,
+            argument: 1.0,
+            trade: 2.0,
+            builtin_call: 1.0,
+            element: 0.5,
+            character: 0.01,
+            input: 0.05,
+            file: 1.0,
+            import: 10.0,
+            constant: 5.0,
+            read: 0.1,
+        }
+    }
+}
+
+impl Default for Operators {
+    fn default() -> Self {
+        Operators {
+            add: 0.0,
+            subtract: 0.0,
+            multiply: 0.0,
+            divide: 0.0,
+            modulo: 0.5,
+            integer_division: 0.5,
+            power: 2.0,
+            bitwise: 0.25,
+            comparison: 0.0,
+        }
+    }
+}
+
+impl Default for DebtCollection {
+    fn default() -> Self {
+        DebtCollection {
+            interval: 20000,
+            rate: 0.05,
+        }
+    }
+}
+
+impl Default for Work {
+    fn default() -> Self {
+        Work {
+            cooldown: 225,
+            pay_rate: 0.001,
+            minimum_pay: 100.0,
+            duration_ms: 300,
+        }
+    }
+}
+
+impl Default for Stocks {
+    fn default() -> Self {
+        Stocks {
+            initial_price: 100.0,
+            tick_interval: 1000,
+            volatility: 0.05,
+            drift: 0.0,
+        }
+    }
+}
+
+impl Default for Loans {
+    fn default() -> Self {
+        Loans {
+            max_multiplier: 5.0,
+        }
+    }
+}
+
+impl Default for Gambling {
+    fn default() -> Self {
+        Gambling { win_chance: 0.5 }
+    }
+}
+
+impl Default for Inflation {
+    fn default() -> Self {
+        Inflation {
+            interval: 1000,
+            rate: 0.0,
+        }
+    }
+}
+
+impl Default for Taxes {
+    fn default() -> Self {
+        Taxes {
+            interval: 10000,
+            rate: 0.1,
+        }
+    }
+}
+
+impl Economy {
+    pub fn preset(name: &str) -> Option<Self> {
+        let default = Economy::default();
+
+        match name {
+            "default" => Some(default),
+            // Plenty of money, cheap features and generous lenders
+            "easy" => Some(Economy {
+                starting_balance: 1000.0,
+                costs: Costs {
+                    print: 0.5,
+                    assignment: 1.0,
+                    if_statement: 1.0,
+                    while_loop: 2.0,
+                    for_loop: 2.0,
+                    procedure_call: 2.0,
+                    argument: 0.5,
+                    trade: 1.0,
+                    builtin_call: 0.5,
+                    element: 0.25,
+                    character: 0.005,
+                    input: 0.025,
+                    file: 0.5,
+                    import: 5.0,
+                    constant: 2.5,
+                    read: 0.05,
+                },
+                work: Work {
+                    cooldown: 100,
+                    ..default.work
+                },
+                loans: Loans {
+                    max_multiplier: 10.0,
+                },
+                ..default
+            }),
+            // Little money, scarce work, aggressive debt collection and a falling stock market
+            "recession" => Some(Economy {
+                starting_balance: 100.0,
+                debt_collection: DebtCollection {
+                    interval: 5000,
+                    rate: 0.1,
+                },
+                work: Work {
+                    cooldown: 500,
+                    minimum_pay: 50.0,
+                    ..default.work
+                },
+                stocks: Stocks {
+                    volatility: 0.5,
+                    drift: -0.1,
+                    ..default.stocks
+                },
+                loans: Loans {
+                    max_multiplier: 2.0,
+                },
+                taxes: Taxes {
+                    interval: 5000,
+                    rate: 0.25,
+                },
+                ..default
+            }),
+            // Every feature gets 1% more expensive every 500 operations
+            "hyperinflation" => Some(Economy {
+                inflation: Inflation {
+                    interval: 500,
+                    rate: 0.01,
+                },
+                ..default
+            }),
+            _ => None,
+        }
+    }
+
+    // Loads an economy profile, JSON if the file ends in .json and TOML otherwise
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read economy profile: {}", err))?;
+
+        let economy: Economy = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents).map_err(|err| err.to_string())?
+        } else {
+            toml::from_str(&contents).map_err(|err| err.to_string())?
+        };
+
+        economy.validate()?;
+
+        Ok(economy)
+    }
+
+    // Rejects values that would crash the VM or make no sense, such as zero length intervals
+    pub fn validate(&self) -> Result<(), String> {
+        let intervals = [
+            ("debt_collection.interval", self.debt_collection.interval),
+            ("work.cooldown", self.work.cooldown),
+            ("stocks.tick_interval", self.stocks.tick_interval),
+            ("inflation.interval", self.inflation.interval),
+            ("taxes.interval", self.taxes.interval),
+        ];
+
+        for (name, interval) in intervals {
+            if interval == 0 {
+                return Err(format!("{} must be at least 1", name));
+            }
+        }
+
+        let (costs, operators) = (&self.costs, &self.operators);
+
+        // A negative price would pay the program for running, so these can't go below zero
+        let prices = [
+            ("costs.print", costs.print),
+            ("costs.assignment", costs.assignment),
+            ("costs.if_statement", costs.if_statement),
+            ("costs.while_loop", costs.while_loop),
+            ("costs.for_loop", costs.for_loop),
+            ("costs.procedure_call", costs.procedure_call),
+            ("costs.argument", costs.argument),
+            ("costs.trade", costs.trade),
+            ("costs.builtin_call", costs.builtin_call),
+            ("costs.element", costs.element),
+            ("costs.character", costs.character),
+            ("costs.input", costs.input),
+            ("costs.file", costs.file),
+            ("costs.import", costs.import),
+            ("costs.constant", costs.constant),
+            ("costs.read", costs.read),
+            ("operators.add", operators.add),
+            ("operators.subtract", operators.subtract),
+            ("operators.multiply", operators.multiply),
+            ("operators.divide", operators.divide),
+            ("operators.modulo", operators.modulo),
+            ("operators.integer_division", operators.integer_division),
+            ("operators.power", operators.power),
+            ("operators.bitwise", operators.bitwise),
+            ("operators.comparison", operators.comparison),
+            ("debt_collection.rate", self.debt_collection.rate),
+            ("work.pay_rate", self.work.pay_rate),
+            ("work.minimum_pay", self.work.minimum_pay),
+            ("stocks.initial_price", self.stocks.initial_price),
+            ("stocks.volatility", self.stocks.volatility),
+            ("loans.max_multiplier", self.loans.max_multiplier),
+        ];
+
+        // These may be negative, a falling market or deflation, but still have to be numbers
+        let others = [
+            ("starting_balance", self.starting_balance),
+            ("stocks.drift", self.stocks.drift),
+            ("gambling.win_chance", self.gambling.win_chance),
+            ("inflation.rate", self.inflation.rate),
+            ("taxes.rate", self.taxes.rate),
+        ];
+
+        for (name, value) in prices.iter().chain(&others) {
+            if !value.is_finite() {
+                return Err(format!("{} must be a finite number", name));
+            }
+        }
+
+        for (name, price) in prices {
+            if price < 0.0 {
+                return Err(format!("{} can't be negative", name));
+            }
+        }
+
+        if !(0.0..=1.0).contains(&self.gambling.win_chance) {
+            return Err("gambling.win_chance must be between 0 and 1".to_string());
+        }
+
+        if !(0.0..=1.0).contains(&self.taxes.rate) {
+            return Err("taxes.rate must be between 0 and 1".to_string());
+        }
+
+        if self.starting_balance <= 0.0 {
+            return Err("starting_balance must be positive".to_string());
+        }
+
+        Ok(())
+    }
+}
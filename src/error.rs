@@ -0,0 +1,136 @@
+use std::fmt;
+
+use crate::lexer::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonopolangError {
+    Lex { message: String, span: Span }, // Invalid characters, unterminated strings
+    Parse { message: String, span: Span }, // Malformed syntax
+    Compile { message: String, span: Span }, // Valid syntax that cannot be compiled
+    Runtime { message: String, span: Span }, // Type errors, undefined variables and procedures
+    Economic { message: String, span: Span }, // Bankruptcy and rejected financial operations
+    Io { message: String, span: Span },  // Reading past the end of input, failed reads
+    Config { message: String }, // Invalid host setup, such as a broken economy, not tied to the source
+}
+
+impl MonopolangError {
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Lex { message, .. }
+            | Self::Parse { message, .. }
+            | Self::Compile { message, .. }
+            | Self::Runtime { message, .. }
+            | Self::Economic { message, .. }
+            | Self::Io { message, .. }
+            | Self::Config { message } => message,
+        }
+    }
+
+    // None for configuration errors, which have no place in the source
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Lex { span, .. }
+            | Self::Parse { span, .. }
+            | Self::Compile { span, .. }
+            | Self::Runtime { span, .. }
+            | Self::Economic { span, .. }
+            | Self::Io { span, .. } => Some(span.clone()),
+            Self::Config { .. } => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Lex { .. } => "lex error",
+            Self::Parse { .. } => "parse error",
+            Self::Compile { .. } => "compile error",
+            Self::Runtime { .. } => "runtime error",
+            Self::Economic { .. } => "economic error",
+            Self::Io { .. } => "io error",
+            Self::Config { .. } => "configuration error",
+        }
+    }
+
+    // Exit codes follow sysexits.h where one fits: 65 for bad programs, 70 for runtime failures, 74 for I/O,
+    // 78 for configuration
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Lex { .. } | Self::Parse { .. } | Self::Compile { .. } => 65,
+            Self::Runtime { .. } => 70,
+            Self::Economic { .. } => 75,
+            Self::Io { .. } => 74,
+            Self::Config { .. } => 78,
+        }
+    }
+
+    // Renders the error with the offending source line and a caret underline, e.g.
+    //
+    // runtime error: Accessing undefined variable 'x'
+    //  --> examples/simple.mp:3:7
+    //   |
+    // 3 | print x
+    //   |       ^
+    //
+    // Errors in imported modules are shown with the module's own path and source instead
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let span = match self.span() {
+            Some(span) => span,
+            None => return format!("{}: {}\n", self.label(), self.message()),
+        };
+        let module_source;
+        let (path, source) = match &span.file {
+            Some(file) => {
+                module_source = std::fs::read_to_string(&**file).unwrap_or_default();
+                (&**file, module_source.as_str())
+            }
+            None => (path, source),
+        };
+        let mut output = format!(
+            "{}: {}\n --> {}:{}:{}\n",
+            self.label(),
+            self.message(),
+            path,
+            span.line,
+            span.column
+        );
+
+        let line = match source.lines().nth((span.line as usize).saturating_sub(1)) {
+            Some(line) => line,
+            None => return output,
+        };
+
+        let gutter = " ".repeat(span.line.to_string().len());
+        let line_length = line.chars().count();
+
+        // Clamp the underline to the visible line, spans may run past it (e.g. at end of file)
+        let start = (span.column as usize).saturating_sub(1).min(line_length);
+        let length = (span.length as usize)
+            .max(1)
+            .min((line_length - start).max(1));
+
+        output += &format!("{} |\n", gutter);
+        output += &format!("{} | {}\n", span.line, line);
+        output += &format!("{} | {}{}\n", gutter, " ".repeat(start), "^".repeat(length));
+
+        output
+    }
+}
+
+impl fmt::Display for MonopolangError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = match self.span() {
+            Some(span) => span,
+            None => return write!(f, "{}: {}", self.label(), self.message()),
+        };
+        write!(
+            f,
+            "{} at <{}:{}>: {}",
+            self.label(),
+            span.line,
+            span.column,
+            self.message()
+        )
+    }
+}
+
+impl std::error::Error for MonopolangError {}
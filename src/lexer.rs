@@ -1,3 +1,7 @@
+use std::sync::Arc;
+
+use crate::error::MonopolangError;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenType {
     // Grouping
@@ -5,12 +9,23 @@ pub enum TokenType {
     RightParen,
     LeftBracket,
     RightBracket,
+    LeftBrace,
+    RightBrace,
 
     // Arithmetic operators
     Plus,
     Minus,
     Star,
     Slash,
+    Percent,
+    StarStar,
+
+    // Bitwise operators
+    Ampersand,
+    Pipe,
+    Caret,
+    LessLess,
+    GreaterGreater,
 
     // Logical operators
     Bang,
@@ -29,23 +44,41 @@ pub enum TokenType {
     True,
     False,
 
+    // String interpolation: "a {x:.2} b" lexes as InterpolationStart, String, LeftBrace,
+    // the tokens of x, FormatSpec, RightBrace, String, InterpolationEnd
+    InterpolationStart,
+    InterpolationEnd,
+    FormatSpec,
+
     // Keywords
     And,
     Or,
+    Div,
     If,
+    Elif,
     Else,
     Then,
     End,
     While,
     Range,
+    For,
+    In,
     From,
     To,
     By,
     Do,
     Procedure,
     Call,
+    Return,
+    Break,
+    Continue,
     Set,
+    Let,
+    Const,
     Print,
+    Import,
+    Input,
+    InputNumber,
 
     // Economy keywords
     Gamble,
@@ -59,6 +92,9 @@ pub enum TokenType {
     At,
     Dollar,
     Arrow,
+    Comma,
+    Colon,
+    Dot,
     Eof,
 }
 
@@ -68,33 +104,68 @@ pub struct Token {
     pub lexeme: String,
     pub line: u32,
     pub column: u32,
+    pub file: Option<Arc<str>>,
+}
+
+// Location of a piece of source code, columns are 1-based like lines
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Span {
+    pub line: u32,
+    pub column: u32,
+    pub length: u32,
+    pub file: Option<Arc<str>>, // Path of an imported module, None for the program itself
+}
+
+impl Token {
+    pub fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+            length: self.lexeme.chars().count() as u32,
+            file: self.file.clone(),
+        }
+    }
 }
 
 pub struct Lexer {
-    source: String,
+    source: Vec<char>, // Indexed by character, so non-ASCII text lexes correctly
     tokens: Vec<Token>,
     line: u32,
     column: u32,
     start: usize,
     current: usize,
+    file: Option<Arc<str>>,
 }
 
 impl Lexer {
     pub fn new(source: String) -> Self {
         Self {
-            source,
+            source: source.chars().collect(),
             tokens: Vec::new(),
             line: 1,
             column: 0,
             start: 0,
             current: 0,
+            file: None,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
+    // Numbers lines from the given line instead of 1, for source that continues earlier input
+    pub fn starting_at_line(mut self, line: u32) -> Self {
+        self.line = line;
+        self
+    }
+
+    // Marks every token and error as coming from an imported module
+    pub fn in_file(mut self, file: Arc<str>) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, MonopolangError> {
         while !self.is_at_end() {
             self.start = self.current;
-            self.scan_token();
+            self.scan_token()?;
         }
 
         self.tokens.push(Token {
@@ -102,12 +173,13 @@ impl Lexer {
             lexeme: String::new(),
             line: self.line,
             column: self.column,
+            file: self.file.clone(),
         });
 
-        return self.tokens.clone();
+        Ok(self.tokens.clone())
     }
 
-    fn scan_token(&mut self) {
+    fn scan_token(&mut self) -> Result<(), MonopolangError> {
         let c = self.advance();
 
         match c {
@@ -120,6 +192,11 @@ impl Lexer {
             ')' => self.add_token(TokenType::RightParen),
             '[' => self.add_token(TokenType::LeftBracket),
             ']' => self.add_token(TokenType::RightBracket),
+            '{' => self.add_token(TokenType::LeftBrace),
+            '}' => self.add_token(TokenType::RightBrace),
+            ',' => self.add_token(TokenType::Comma),
+            ':' => self.add_token(TokenType::Colon),
+            '.' => self.add_token(TokenType::Dot),
             '@' => self.add_token(TokenType::At),
             '$' => self.add_token(TokenType::Dollar),
             '+' => self.add_token(TokenType::Plus),
@@ -130,7 +207,17 @@ impl Lexer {
                     self.add_token(TokenType::Minus)
                 }
             }
-            '*' => self.add_token(TokenType::Star),
+            '*' => {
+                if self.match_char('*') {
+                    self.add_token(TokenType::StarStar)
+                } else {
+                    self.add_token(TokenType::Star)
+                }
+            }
+            '%' => self.add_token(TokenType::Percent),
+            '&' => self.add_token(TokenType::Ampersand),
+            '|' => self.add_token(TokenType::Pipe),
+            '^' => self.add_token(TokenType::Caret),
             '/' => {
                 if self.match_char('/') {
                     while self.peek() != '\n' && !self.is_at_end() {
@@ -155,25 +242,31 @@ impl Lexer {
                 }
             }
             '<' => {
-                if self.match_char('=') {
+                if self.match_char('<') {
+                    self.add_token(TokenType::LessLess)
+                } else if self.match_char('=') {
                     self.add_token(TokenType::LessEqual)
                 } else {
                     self.add_token(TokenType::Less)
                 }
             }
             '>' => {
-                if self.match_char('=') {
+                if self.match_char('>') {
+                    self.add_token(TokenType::GreaterGreater)
+                } else if self.match_char('=') {
                     self.add_token(TokenType::GreaterEqual)
                 } else {
                     self.add_token(TokenType::Greater)
                 }
             }
-            '"' => self.string(),
+            '"' => self.string()?,
             '0'..='9' => self.number(),
             'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
             '\0' => (),
-            _ => self.error(&format!("Unexpected character: {}", c)),
+            _ => return Err(self.error(&format!("Unexpected character: {}", c))),
         }
+
+        Ok(())
     }
 
     fn identifier(&mut self) {
@@ -181,59 +274,174 @@ impl Lexer {
             self.advance();
         }
 
-        let kind = match self.source[self.start..self.current].to_string().as_str() {
-            "and" => TokenType::And,
-            "or" => TokenType::Or,
-            "if" => TokenType::If,
-            "else" => TokenType::Else,
-            "then" => TokenType::Then,
-            "end" => TokenType::End,
-            "while" => TokenType::While,
-            "range" => TokenType::Range,
-            "from" => TokenType::From,
-            "to" => TokenType::To,
-            "by" => TokenType::By,
-            "do" => TokenType::Do,
-            "proc" => TokenType::Procedure,
-            "call" => TokenType::Call,
-            "set" => TokenType::Set,
-            "print" => TokenType::Print,
-            "true" => TokenType::True,
-            "false" => TokenType::False,
-            "gamble" => TokenType::Gamble,
-            "buy" => TokenType::Buy,
-            "sell" => TokenType::Sell,
-            "loan" => TokenType::Loan,
-            "repay" => TokenType::Repay,
-            "work" => TokenType::Work,
-            _ => TokenType::Identifier,
-        };
-
+        let kind = keyword(&self.lexeme()).unwrap_or(TokenType::Identifier);
         self.add_token(kind);
     }
 
-    fn string(&mut self) {
+    fn string(&mut self) -> Result<(), MonopolangError> {
         self.start += 1; // Skip the opening quote
 
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
-                self.column = 1;
+        // Remember where the string started, so an unterminated string points at its opening quote
+        let (line, column) = (self.line, self.column);
+
+        // Embedded expressions may contain strings of their own, so quotes inside braces don't end the string
+        let mut depth = 0;
+        let mut nested = false;
+
+        loop {
+            if self.is_at_end() {
+                let message = if depth > 0 {
+                    "Unterminated string, a '{' in it is never closed (use '{{' for a literal brace)"
+                } else {
+                    "Unterminated string"
+                };
+                return Err(self.error_at(message, line, column));
+            }
+            if self.peek() == '"' && depth == 0 {
+                break;
+            }
+
+            let c = self.advance();
+            match c {
+                '\n' => {
+                    self.line += 1;
+                    self.column = 0;
+                }
+                '"' => nested = !nested,
+                _ if nested => (),
+                '{' if depth == 0 && self.peek() == '{' => {
+                    self.advance();
+                }
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                _ => (),
             }
-            self.advance();
         }
-        if self.is_at_end() {
-            self.error("Unterminated string");
+
+        if self.source[self.start..self.current]
+            .iter()
+            .any(|&c| c == '{' || c == '}')
+        {
+            self.interpolation(line, column)?;
+        } else {
+            // The string may span lines, so its start can't be worked out from where it ends
+            let lexeme = self.lexeme();
+            self.push_token(TokenType::String, &lexeme, line, column + 1);
         }
-        self.add_token(TokenType::String);
         self.advance(); // Consume the closing quote
+
+        Ok(())
+    }
+
+    // Splits a string with embedded expressions into literal parts and the tokens of each expression
+    // '{{' and '}}' stand for literal braces, a ':' after the expression starts a format spec
+    fn interpolation(&mut self, line: u32, column: u32) -> Result<(), MonopolangError> {
+        let content = self.source[self.start..self.current].to_vec();
+        let mut position = (line, column); // Line and column of the character before content[i]
+        let mut literal = String::new();
+        let mut literal_start = position;
+        let mut i = 0;
+
+        self.push_token(TokenType::InterpolationStart, "\"", line, column);
+
+        while i < content.len() {
+            let c = content[i];
+            let escaped = (c == '{' || c == '}') && content.get(i + 1) == Some(&c);
+
+            if c == '}' && !escaped {
+                return Err(self.error_at(
+                    "Unmatched '}' in string, use '}}' for a literal brace",
+                    position.0,
+                    position.1 + 1,
+                ));
+            }
+            if c != '{' || escaped {
+                if literal.is_empty() {
+                    literal_start = (position.0, position.1 + 1);
+                }
+                literal.push(c);
+
+                let length = if escaped { 2 } else { 1 };
+                advance_position(&mut position, &content[i..i + length]);
+                i += length;
+                continue;
+            }
+
+            self.literal_token(&mut literal, literal_start);
+
+            let (close, colon) = match embedded_end(&content, i) {
+                Some(end) => end,
+                None => {
+                    return Err(self.error_at(
+                        "Unterminated '{' in string, use '{{' for a literal brace",
+                        position.0,
+                        position.1 + 1,
+                    ))
+                }
+            };
+            let end = colon.unwrap_or(close);
+            let expression: String = content[i + 1..end].iter().collect();
+
+            advance_position(&mut position, &content[i..=i]);
+            self.push_token(TokenType::LeftBrace, "{", position.0, position.1);
+
+            if expression.trim().is_empty() {
+                return Err(self.error_at(
+                    "Expected expression inside '{}' in string",
+                    position.0,
+                    position.1,
+                ));
+            }
+
+            // Lex the expression on its own, at its place in the string so errors point inside it
+            let mut lexer = Lexer::new(expression);
+            lexer.line = position.0;
+            lexer.column = position.1;
+            lexer.file = self.file.clone();
+            let tokens = lexer.scan_tokens()?;
+            self.tokens.extend(
+                tokens
+                    .into_iter()
+                    .filter(|token| token.kind != TokenType::Eof),
+            );
+            advance_position(&mut position, &content[i + 1..end]);
+
+            if let Some(colon) = colon {
+                let spec: String = content[colon + 1..close].iter().collect();
+                advance_position(&mut position, &content[colon..=colon]);
+                self.push_token(TokenType::FormatSpec, &spec, position.0, position.1 + 1);
+                advance_position(&mut position, &content[colon + 1..close]);
+            }
+
+            advance_position(&mut position, &content[close..=close]);
+            self.push_token(TokenType::RightBrace, "}", position.0, position.1);
+            i = close + 1;
+        }
+
+        self.literal_token(&mut literal, literal_start);
+        self.push_token(
+            TokenType::InterpolationEnd,
+            "\"",
+            position.0,
+            position.1 + 1,
+        );
+
+        Ok(())
+    }
+
+    // Adds the literal text collected so far as a string token, if there is any
+    fn literal_token(&mut self, literal: &mut String, (line, column): (u32, u32)) {
+        if !literal.is_empty() {
+            self.push_token(TokenType::String, literal, line, column);
+            literal.clear();
+        }
     }
 
     fn number(&mut self) {
         while self.is_digit() {
             self.advance();
         }
-        if self.peek() == '.' && self.is_digit() {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
             while self.is_digit() {
                 self.advance();
@@ -243,21 +451,24 @@ impl Lexer {
     }
 
     fn advance(&mut self) -> char {
+        // Read before moving on, so the last character of source without a trailing newline isn't lost
+        let c = self.peek();
+
         self.current += 1;
         self.column += 1;
 
-        if self.is_at_end() {
-            return '\0';
-        }
-
-        self.source.chars().nth(self.current as usize - 1).unwrap()
+        c
     }
 
     fn peek(&self) -> char {
         if self.is_at_end() {
             return '\0';
         }
-        self.source.chars().nth(self.current as usize).unwrap()
+        self.source[self.current]
+    }
+
+    fn peek_next(&self) -> char {
+        self.source.get(self.current + 1).copied().unwrap_or('\0')
     }
 
     fn match_char(&mut self, expected: char) -> bool {
@@ -273,34 +484,132 @@ impl Lexer {
     }
 
     fn add_token(&mut self, kind: TokenType) {
-        let text = self.source[self.start..self.current].to_string();
-        let length = text.len() as u32;
+        let text = self.lexeme();
+        let length = text.chars().count() as u32;
 
         self.tokens.push(Token {
             kind,
             lexeme: text,
             line: self.line,
             column: self.column - length + 1, // Subtract length to get the start of the token
+            file: self.file.clone(),
         });
     }
 
+    fn push_token(&mut self, kind: TokenType, lexeme: &str, line: u32, column: u32) {
+        self.tokens.push(Token {
+            kind,
+            lexeme: lexeme.to_string(),
+            line,
+            column,
+            file: self.file.clone(),
+        });
+    }
+
+    fn lexeme(&self) -> String {
+        self.source[self.start..self.current].iter().collect()
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 
     fn is_digit(&self) -> bool {
-        self.peek().is_digit(10)
-    }
-
-    fn is_alpha(&self) -> bool {
-        self.peek().is_alphabetic()
+        self.peek().is_ascii_digit()
     }
 
     fn is_identifier(&self) -> bool {
         self.peek().is_alphabetic() || self.peek() == '_'
     }
 
-    fn error(&self, message: &str) -> ! {
-        panic!("Error at <{}:{}>: {}", self.line, self.column, message);
+    fn error(&self, message: &str) -> MonopolangError {
+        self.error_at(message, self.line, self.column)
+    }
+
+    fn error_at(&self, message: &str, line: u32, column: u32) -> MonopolangError {
+        MonopolangError::Lex {
+            message: message.to_string(),
+            span: Span {
+                line,
+                column,
+                length: 1,
+                file: self.file.clone(),
+            },
+        }
+    }
+}
+
+// Reserved words, which can't be used as names
+pub fn keyword(name: &str) -> Option<TokenType> {
+    match name {
+        "and" => Some(TokenType::And),
+        "or" => Some(TokenType::Or),
+        "div" => Some(TokenType::Div),
+        "if" => Some(TokenType::If),
+        "elif" => Some(TokenType::Elif),
+        "else" => Some(TokenType::Else),
+        "then" => Some(TokenType::Then),
+        "end" => Some(TokenType::End),
+        "while" => Some(TokenType::While),
+        "range" => Some(TokenType::Range),
+        "for" => Some(TokenType::For),
+        "in" => Some(TokenType::In),
+        "from" => Some(TokenType::From),
+        "to" => Some(TokenType::To),
+        "by" => Some(TokenType::By),
+        "do" => Some(TokenType::Do),
+        "proc" => Some(TokenType::Procedure),
+        "call" => Some(TokenType::Call),
+        "return" => Some(TokenType::Return),
+        "break" => Some(TokenType::Break),
+        "continue" => Some(TokenType::Continue),
+        "set" => Some(TokenType::Set),
+        "let" => Some(TokenType::Let),
+        "const" => Some(TokenType::Const),
+        "print" => Some(TokenType::Print),
+        "import" => Some(TokenType::Import),
+        "input" => Some(TokenType::Input),
+        "input_number" => Some(TokenType::InputNumber),
+        "true" => Some(TokenType::True),
+        "false" => Some(TokenType::False),
+        "gamble" => Some(TokenType::Gamble),
+        "buy" => Some(TokenType::Buy),
+        "sell" => Some(TokenType::Sell),
+        "loan" => Some(TokenType::Loan),
+        "repay" => Some(TokenType::Repay),
+        "work" => Some(TokenType::Work),
+        _ => None,
+    }
+}
+// Moves a line and column past the given characters
+fn advance_position(position: &mut (u32, u32), characters: &[char]) {
+    for &c in characters {
+        if c == '\n' {
+            *position = (position.0 + 1, 0);
+        } else {
+            position.1 += 1;
+        }
     }
 }
+
+// Finds the '}' closing the embedded expression that starts at content[open], and the ':' of its format spec
+// Braces and colons inside the expression's own strings and map literals are skipped
+fn embedded_end(content: &[char], open: usize) -> Option<(usize, Option<usize>)> {
+    let mut depth = 0;
+    let mut nested = false;
+    let mut colon = None;
+
+    for (i, &c) in content.iter().enumerate().skip(open + 1) {
+        match c {
+            '"' => nested = !nested,
+            _ if nested => (),
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            '}' => return Some((i, colon)),
+            ':' if depth == 0 && colon.is_none() => colon = Some(i),
+            _ => (),
+        }
+    }
+
+    None
+}
@@ -0,0 +1,198 @@
+// Monopolang as a library, so Rust programs can run monopolang code and inspect the economy afterwards
+//
+// let report = Interpreter::new("print 1 + 2").seed(42).run()?;
+// assert_eq!(report.stdout, "3\n");
+
+mod ast;
+mod builtins;
+mod compiler;
+#[allow(dead_code)] // Only used when debugging the parser
+mod debug;
+pub mod economy;
+mod error;
+mod lexer;
+mod parser;
+mod repl;
+mod sandbox;
+mod tax;
+mod trace;
+mod value;
+mod vm;
+
+use std::{
+    cell::RefCell,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+pub use economy::Economy;
+pub use error::MonopolangError;
+pub use lexer::Span;
+pub use repl::Repl;
+pub use sandbox::Sandbox;
+pub use trace::{TraceFilter, TraceFormat, Tracer};
+pub use value::Value;
+pub use vm::{Holding, NativeFunction, Summary};
+
+// Builds and runs a single program
+pub struct Interpreter {
+    source: String,
+    path: Option<PathBuf>,
+    economy: Economy,
+    seed: Option<u64>,
+    output: Option<Box<dyn Write>>,
+    input: Option<Box<dyn BufRead>>,
+    sandbox: Option<Sandbox>,
+    tracer: Option<Tracer>,
+    natives: Vec<(String, usize, f64, NativeFunction)>, // Name, Arity, Price, Function
+}
+
+// Everything a host might want to know about a finished run
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunReport {
+    pub summary: Summary, // Final balance, debt, portfolio, taxes and operation count
+    pub stdout: String,   // Everything the program printed, empty when output went to a custom sink
+    pub seed: u64,        // Seed the run used, to replay it
+}
+
+impl Interpreter {
+    pub fn new(source: impl Into<String>) -> Self {
+        Interpreter {
+            source: source.into(),
+            path: None,
+            economy: Economy::default(),
+            seed: None,
+            output: None,
+            input: None,
+            sandbox: None,
+            tracer: None,
+            natives: Vec::new(),
+        }
+    }
+
+    // File the source was read from, imports are resolved relative to it instead of the current directory
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn economy(mut self, economy: Economy) -> Self {
+        self.economy = economy;
+        self
+    }
+
+    // Without a seed every run picks a random one, it is reported in the RunReport
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // Sends printed output to the given writer instead of collecting it in RunReport::stdout
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    // Lets input and input_number read from the given reader, without one they hit the end of input
+    pub fn input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    // Allows read_file, write_file and append_file inside the sandbox's directory
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    pub fn tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    // Exposes a Rust function to the program, called like a procedure: `print quote("AAPL")`
+    // Every call costs the given price on top of the usual procedure call costs
+    // Builtin names such as `len` can't be used, running fails with a configuration error instead
+    pub fn native(
+        mut self,
+        name: impl Into<String>,
+        arity: usize,
+        price: f64,
+        function: impl FnMut(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Self {
+        self.natives
+            .push((name.into(), arity, price, Box::new(function)));
+        self
+    }
+
+    pub fn run(self) -> Result<RunReport, MonopolangError> {
+        let seed = self.seed.unwrap_or_else(rand::random);
+
+        // A host may build the economy by hand, and some values (zero intervals) would crash the VM
+        self.economy
+            .validate()
+            .map_err(|message| MonopolangError::Config {
+                message: format!("Invalid economy: {}", message),
+            })?;
+
+        let tokens = lexer::Lexer::new(self.source).scan_tokens()?;
+        let ast = parser::Parser::new(tokens).parse()?;
+
+        let mut vm = vm::VM::new(self.economy, seed);
+        vm.tracer = self.tracer;
+        vm.sandbox = self.sandbox;
+
+        for (name, arity, price, function) in self.natives {
+            vm.register_native(name, arity, price, function)
+                .map_err(|message| MonopolangError::Config { message })?;
+        }
+
+        if let Some(input) = self.input {
+            vm.input = input;
+        }
+
+        let captured = SharedBuffer::default();
+        vm.output = match self.output {
+            Some(output) => output,
+            None => Box::new(captured.clone()),
+        };
+
+        let directory = self
+            .path
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        let mut compiler = compiler::Compiler::new(ast, vm).in_directory(directory);
+        if let Some(path) = &self.path {
+            compiler = compiler.in_file(path);
+        }
+        compiler.compile()?;
+        compiler.vm.execute()?;
+
+        let stdout = String::from_utf8_lossy(&captured.0.borrow()).into_owned();
+
+        Ok(RunReport {
+            summary: compiler.vm.summary(),
+            stdout,
+            seed,
+        })
+    }
+}
+
+// Collects output in memory while the VM owns a handle to it
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
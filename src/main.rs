@@ -1,38 +1,207 @@
-mod ast;
-mod compiler;
-mod debug;
-mod lexer;
-mod parser;
-mod value;
-mod vm;
+use monopolang::{
+    economy, Economy, Interpreter, MonopolangError, Repl, Sandbox, TraceFilter, TraceFormat, Tracer,
+};
+
+const USAGE: &str = "Usage: monopolang [options] <file>
+       monopolang repl [options]
+
+Options:
+  --economy <path>         Load the economy model from a TOML or JSON profile
+  --preset <name>          Use a built-in economy: default, easy, recession, hyperinflation
+  --seed <number>          Seed for gambling and stock prices, to replay a previous run
+  --sandbox <dir>          Let read_file, write_file and append_file use files in this directory
+  --trace                  Print every executed instruction to stderr
+  --trace-filter <filter>  Only trace matching instructions, can be given more than once:
+                             economy      economy opcodes (costs, loans, trades, ...)
+                             balance      instructions that changed the balance
+                             proc=<name>  instructions inside the named procedure
+  --trace-file <path>      Write the trace to a file as JSON lines instead of stderr";
+
+#[derive(Default)]
+struct Options {
+    path: String,
+    repl: bool,
+    economy: Economy,
+    seed: Option<u64>,
+    sandbox: Option<Sandbox>,
+    trace: bool,
+    trace_filters: Vec<TraceFilter>,
+    trace_file: Option<String>,
+}
 
 fn main() {
-    // Read the source code from a file
-    // File given by command line argument
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: monopolang [file]");
-        std::process::exit(1);
+    let options = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(64);
+        }
+    };
+
+    let tracer = match create_tracer(&options) {
+        Ok(tracer) => tracer,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(73);
+        }
+    };
+
+    // Pick a seed up front when none is given, so it can be reported if the program crashes
+    let seed = options.seed.unwrap_or_else(rand::random);
+
+    if options.repl {
+        let mut repl = Repl::new(options.economy, seed, tracer);
+        if let Some(sandbox) = options.sandbox {
+            repl = repl.sandbox(sandbox);
+        }
+
+        repl.run(std::io::stdin().lock(), std::io::stdout());
+        return;
+    }
+
+    // Read the source code from the file given on the command line
+    let path = &options.path;
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Failed to read '{}': {}", path, err);
+            std::process::exit(66);
+        }
+    };
+
+    let mut interpreter = Interpreter::new(source.clone())
+        .path(path)
+        .economy(options.economy)
+        .seed(seed)
+        .output(std::io::stdout())
+        .input(std::io::stdin().lock());
+
+    if let Some(tracer) = tracer {
+        interpreter = interpreter.tracer(tracer);
     }
+    if let Some(sandbox) = options.sandbox {
+        interpreter = interpreter.sandbox(sandbox);
+    }
+
+    match interpreter.run() {
+        Ok(report) => eprintln!("\n--- summary ---\n{}", report.summary),
+        Err(err) => {
+            eprint!("{}", err.render(path, &source));
+
+            if matches!(
+                err,
+                MonopolangError::Runtime { .. } | MonopolangError::Economic { .. }
+            ) {
+                eprintln!("seed: {} (replay this run with --seed {})", seed, seed);
+            }
+
+            std::process::exit(err.exit_code());
+        }
+    }
+}
 
-    let source = std::fs::read_to_string(&args[1]).expect("Failed to read file");
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut path = None;
+    let mut economy_source: Option<String> = None;
+    let mut args = args.into_iter().peekable();
 
-    // Create a lexer
-    let mut lexer = lexer::Lexer::new(source);
-    let tokens = lexer.scan_tokens();
+    if args.peek().is_some_and(|arg| arg == "repl") {
+        args.next();
+        options.repl = true;
+    }
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--economy" | "--preset" if economy_source.is_some() => {
+                return Err("Only one of --economy and --preset can be given".to_string())
+            }
+            "--economy" => {
+                let file = args.next().ok_or("Missing value for --economy")?;
+
+                options.economy = Economy::load(std::path::Path::new(&file))
+                    .map_err(|err| format!("Invalid economy profile '{}': {}", file, err))?;
+                economy_source = Some(file);
+            }
+            "--preset" => {
+                let name = args.next().ok_or("Missing value for --preset")?;
+
+                options.economy = Economy::preset(&name).ok_or(format!(
+                    "Unknown economy preset '{}', expected one of: {}",
+                    name,
+                    economy::PRESETS.join(", ")
+                ))?;
+                economy_source = Some(name);
+            }
+            "--seed" => {
+                let seed = args.next().ok_or("Missing value for --seed")?;
+                let seed = seed
+                    .parse()
+                    .map_err(|_| format!("Invalid seed '{}', expected a whole number", seed))?;
+
+                options.seed = Some(seed);
+            }
+            "--sandbox" => {
+                let directory = args.next().ok_or("Missing value for --sandbox")?;
+                let sandbox = Sandbox::new(&directory)
+                    .map_err(|err| format!("Invalid sandbox '{}': {}", directory, err))?;
+
+                options.sandbox = Some(sandbox);
+            }
+            "--trace" => options.trace = true,
+            "--trace-filter" => {
+                let filter = args.next().ok_or("Missing value for --trace-filter")?;
+                let filter = TraceFilter::parse(&filter)
+                    .ok_or(format!("Unknown trace filter '{}'", filter))?;
+
+                options.trace = true;
+                options.trace_filters.push(filter);
+            }
+            "--trace-file" => {
+                let file = args.next().ok_or("Missing value for --trace-file")?;
+
+                options.trace = true;
+                options.trace_file = Some(file);
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'", arg)),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg)),
+        }
+    }
+
+    if options.repl {
+        if let Some(path) = path {
+            return Err(format!("Unexpected argument '{}'", path));
+        }
+
+        return Ok(options);
+    }
+
+    options.path = path.ok_or("Missing file to run")?;
+
+    Ok(options)
+}
+
+fn create_tracer(options: &Options) -> Result<Option<Tracer>, String> {
+    if !options.trace {
+        return Ok(None);
+    }
 
-    // Create a parser
-    let mut parser = parser::Parser::new(tokens);
-    let ast = parser.parse();
+    let filters = options.trace_filters.clone();
 
-    // for decl in &ast {
-    //     debug::traverse_print(decl);
-    // }
+    let tracer = match &options.trace_file {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .map_err(|err| format!("Failed to create trace file '{}': {}", path, err))?;
 
-    // Compile the AST
-    let mut compiler = compiler::Compiler::new(ast, vm::VM::new());
-    compiler.compile();
+            Tracer::new(
+                filters,
+                TraceFormat::Json,
+                Box::new(std::io::BufWriter::new(file)),
+            )
+        }
+        None => Tracer::new(filters, TraceFormat::Text, Box::new(std::io::stderr())),
+    };
 
-    // Run the VM
-    compiler.vm.execute();
+    Ok(Some(tracer))
 }
@@ -1,6 +1,11 @@
 use crate::{
-    ast::{BinaryOperator, Declaration, Expression, LogicalOperator, Statement, UnaryOperator},
-    lexer::{Token, TokenType},
+    ast::{
+        BinaryOperator, Declaration, Expression, InterpolationPart, LogicalOperator, Statement,
+        StatementKind, UnaryOperator,
+    },
+    builtins::Builtin,
+    error::MonopolangError,
+    lexer::{Span, Token, TokenType},
 };
 
 pub struct Parser {
@@ -13,230 +18,415 @@ impl Parser {
         Self { tokens, current: 0 }
     }
 
-    pub fn parse(&mut self) -> Vec<Declaration> {
+    pub fn parse(&mut self) -> Result<Vec<Declaration>, MonopolangError> {
         let mut declarations = Vec::new();
 
         while !self.is_at_end() {
-            declarations.push(self.declaration());
+            declarations.push(self.declaration()?);
         }
 
-        declarations
+        Ok(declarations)
     }
 
-    pub fn declaration(&mut self) -> Declaration {
+    pub fn declaration(&mut self) -> Result<Declaration, MonopolangError> {
         if self.match_token(TokenType::Procedure) {
             self.procedure_declaration()
+        } else if self.check(TokenType::Import) {
+            self.import_declaration()
+        } else if self.check(TokenType::Const) {
+            self.constant_declaration()
         } else {
-            Declaration::Statement(self.statement())
+            Ok(Declaration::Statement(self.statement()?))
         }
     }
 
-    pub fn procedure_declaration(&mut self) -> Declaration {
-        let name = self
-            .consume(TokenType::Identifier, "Expected procedure name")
+    pub fn import_declaration(&mut self) -> Result<Declaration, MonopolangError> {
+        let start = self.current;
+        self.advance();
+
+        let path = self
+            .consume(TokenType::String, "Expected a file path after 'import'")?
             .lexeme;
 
-        self.consume(TokenType::Do, "Expected 'do' after procedure name");
+        Ok(Declaration::Import(path, self.span_from(start)))
+    }
 
-        let code = self.block();
+    pub fn constant_declaration(&mut self) -> Result<Declaration, MonopolangError> {
+        let start = self.current;
+        self.advance();
 
-        Declaration::Procedure(name, code)
+        let name = self
+            .consume(TokenType::Identifier, "Expected constant name")?
+            .lexeme;
+        self.consume(TokenType::Arrow, "Expected '->' after constant name")?;
+        let value = self.expression()?;
+
+        Ok(Declaration::Constant(name, value, self.span_from(start)))
     }
 
-    pub fn statement(&mut self) -> Statement {
-        match self.peek().kind {
-            TokenType::Set => self.variable_assignment_statement(),
-            TokenType::Print => self.print_statement(),
-            TokenType::If => self.if_statement(),
-            TokenType::While => self.while_statement(),
-            TokenType::Range => self.range_statement(),
-            TokenType::Call => self.procedure_call_statement(),
-            TokenType::Gamble => self.gamble_statement(),
-            TokenType::Buy => self.buy_statement(),
-            TokenType::Sell => self.sell_statement(),
-            TokenType::Loan => self.loan_statement(),
-            TokenType::Repay => self.pay_statement(),
-            TokenType::Work => self.work_statement(),
-            _ => Statement::Expression(self.expression()),
+    pub fn procedure_declaration(&mut self) -> Result<Declaration, MonopolangError> {
+        let name = self
+            .consume(TokenType::Identifier, "Expected procedure name")?
+            .lexeme;
+
+        if Builtin::from_name(&name).is_some() {
+            return Err(self.error_at_previous(&format!(
+                "Cannot name a procedure '{}', it is a builtin function",
+                name
+            )));
+        }
+
+        let mut parameters: Vec<String> = Vec::new();
+
+        if self.match_token(TokenType::LeftParen) {
+            if !self.check(TokenType::RightParen) {
+                loop {
+                    let parameter = self
+                        .consume(TokenType::Identifier, "Expected parameter name")?
+                        .lexeme;
+
+                    if parameters.contains(&parameter) {
+                        return Err(self.error_at_previous("Duplicate parameter name"));
+                    }
+
+                    parameters.push(parameter);
+
+                    if !self.match_token(TokenType::Comma) {
+                        break;
+                    }
+                }
+            }
+
+            self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
         }
+
+        self.consume(TokenType::Do, "Expected 'do' after procedure name")?;
+
+        let code = self.block()?;
+
+        Ok(Declaration::Procedure(name, parameters, code))
+    }
+
+    pub fn statement(&mut self) -> Result<Statement, MonopolangError> {
+        let start = self.current;
+
+        let kind = match self.peek().kind {
+            TokenType::Set => self.variable_assignment_statement()?,
+            TokenType::Let => self.let_statement()?,
+            TokenType::Print => self.print_statement()?,
+            TokenType::If => self.if_statement()?,
+            TokenType::While => self.while_statement()?,
+            TokenType::Range => self.range_statement()?,
+            TokenType::For => self.for_statement()?,
+            TokenType::Call => self.procedure_call_statement()?,
+            TokenType::Return => self.return_statement()?,
+            TokenType::Gamble => self.gamble_statement()?,
+            TokenType::Buy => self.buy_statement()?,
+            TokenType::Sell => self.sell_statement()?,
+            TokenType::Loan => self.loan_statement()?,
+            TokenType::Repay => self.pay_statement()?,
+            TokenType::Work => self.work_statement()?,
+            TokenType::Break => {
+                self.advance();
+                StatementKind::Break
+            }
+            TokenType::Continue => {
+                self.advance();
+                StatementKind::Continue
+            }
+            TokenType::Const => return Err(self.error(
+                "Constants can only be declared at the top level, outside of blocks and procedures",
+            )),
+            _ => StatementKind::Expression(self.expression()?),
+        };
+
+        Ok(Statement::new(kind, self.span_from(start)))
     }
 
-    pub fn variable_assignment_statement(&mut self) -> Statement {
+    pub fn let_statement(&mut self) -> Result<StatementKind, MonopolangError> {
+        self.advance();
+
+        let name = self
+            .consume(TokenType::Identifier, "Expected variable name after 'let'")?
+            .lexeme;
+        self.consume(TokenType::Arrow, "Expected '->' after variable name")?;
+
+        Ok(StatementKind::Let(name, self.expression()?))
+    }
+
+    pub fn variable_assignment_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
 
         // If the next token is an at, it's a readonly variable and we should use a specialized error message
         if self.check(TokenType::At) {
-            self.error("Cannot declare readonly variable");
+            return Err(self.error("Cannot declare readonly variable"));
         }
 
         let name = self
-            .consume(TokenType::Identifier, "Expected variable name")
+            .consume(TokenType::Identifier, "Expected variable name")?
             .lexeme;
-        self.consume(TokenType::Arrow, "Expected '->' after variable name");
 
-        let initializer = self.expression();
+        // set xs[i] -> value, with every index but the last selecting the list to change
+        let mut indices = Vec::new();
+        while self.match_token(TokenType::LeftBracket) {
+            indices.push(self.expression()?);
+            self.consume(TokenType::RightBracket, "Expected ']' after index")?;
+        }
+
+        self.consume(TokenType::Arrow, "Expected '->' after variable name")?;
+
+        let initializer = self.expression()?;
 
-        Statement::VariableAssignment(name, initializer)
+        match indices.pop() {
+            Some(index) => {
+                let list = indices
+                    .into_iter()
+                    .fold(Expression::Variable(name), |list, index| {
+                        Expression::Index(Box::new(list), Box::new(index))
+                    });
+
+                Ok(StatementKind::IndexAssignment(list, index, initializer))
+            }
+            None => Ok(StatementKind::VariableAssignment(name, initializer)),
+        }
     }
 
-    pub fn print_statement(&mut self) -> Statement {
+    pub fn print_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
-        let value = self.expression();
+        let value = self.expression()?;
 
-        Statement::Print(value)
+        Ok(StatementKind::Print(value))
     }
 
-    pub fn if_statement(&mut self) -> Statement {
+    pub fn if_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
 
-        let condition = self.expression();
+        let mut branches = Vec::new();
+
+        loop {
+            let condition = self.expression()?;
+
+            self.consume(TokenType::Then, "Expected 'then' after if condition")?;
 
-        self.consume(TokenType::Then, "Expected 'then' after if condition");
+            branches.push((condition, self.block_statement(Self::if_block)?));
+
+            // Each elif adds another condition and branch, sharing the if's 'end'
+            // Previous token and not current because block consumes the 'elif' token
+            if self.previous().kind != TokenType::Elif {
+                break;
+            }
+        }
 
-        let then_branch = Box::new(Statement::Block(self.if_block()));
         let mut else_branch: Option<Box<Statement>> = None;
 
         // If previous token was an 'else', we have an else branch
-        // Previous token and not current because block consumes the 'else' token
         if self.previous().kind == TokenType::Else {
-            else_branch = Some(Box::new(Statement::Block(self.block())))
+            else_branch = Some(Box::new(self.block_statement(Self::block)?))
         }
 
-        Statement::If(condition, then_branch, else_branch)
+        Ok(StatementKind::If(branches, else_branch))
     }
 
-    pub fn while_statement(&mut self) -> Statement {
+    pub fn while_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
 
-        let condition = self.expression();
+        let condition = self.expression()?;
 
-        self.consume(TokenType::Do, "Expected 'do' after while condition");
+        self.consume(TokenType::Do, "Expected 'do' after while condition")?;
 
-        Statement::While(condition, Box::new(Statement::Block(self.block())))
+        Ok(StatementKind::While(
+            condition,
+            Box::new(self.block_statement(Self::block)?),
+        ))
     }
 
-    pub fn range_statement(&mut self) -> Statement {
+    pub fn range_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
 
         let name = self
-            .consume(TokenType::Identifier, "Expected variable name")
+            .consume(TokenType::Identifier, "Expected variable name")?
             .lexeme;
 
-        self.consume(TokenType::From, "Expected 'from' after variable name");
+        self.consume(TokenType::From, "Expected 'from' after variable name")?;
 
-        let start = self.expression();
+        let start = self.expression()?;
 
-        self.consume(TokenType::To, "Expected 'to' after range start");
+        self.consume(TokenType::To, "Expected 'to' after range start")?;
 
-        let end = self.expression();
+        let end = self.expression()?;
 
         let step = if self.match_token(TokenType::By) {
-            self.expression()
+            self.expression()?
         } else {
             Expression::Number(1.0)
         };
 
-        self.consume(TokenType::Do, "Expected 'do' after range");
+        self.consume(TokenType::Do, "Expected 'do' after range")?;
 
-        Statement::Range(
+        Ok(StatementKind::Range(
             name,
             start,
             end,
             step,
-            Box::new(Statement::Block(self.block())),
-        )
+            Box::new(self.block_statement(Self::block)?),
+        ))
     }
 
-    pub fn procedure_call_statement(&mut self) -> Statement {
+    pub fn for_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
+
         let name = self
-            .consume(TokenType::Identifier, "Expected procedure name")
+            .consume(TokenType::Identifier, "Expected variable name")?
             .lexeme;
 
-        Statement::ProcedureCall(name)
+        self.consume(TokenType::In, "Expected 'in' after variable name")?;
+
+        let collection = self.expression()?;
+
+        self.consume(TokenType::Do, "Expected 'do' after for collection")?;
+
+        Ok(StatementKind::ForEach(
+            name,
+            collection,
+            Box::new(self.block_statement(Self::block)?),
+        ))
     }
 
-    pub fn gamble_statement(&mut self) -> Statement {
+    pub fn procedure_call_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
-        let value = self.expression();
+        let name = self
+            .consume(TokenType::Identifier, "Expected procedure name")?
+            .lexeme;
+        let name = self.qualified_name(name)?;
 
-        Statement::Gamble(value)
+        let arguments = if self.match_token(TokenType::LeftParen) {
+            self.arguments()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(StatementKind::ProcedureCall(name, arguments))
     }
 
-    pub fn buy_statement(&mut self) -> Statement {
+    pub fn return_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
-        let stock = self.expression();
-        let amount = self.expression();
 
-        Statement::Buy(stock, amount)
+        // A bare 'return' at the end of a block returns void
+        let value = if self.check(TokenType::End)
+            || self.check(TokenType::Elif)
+            || self.check(TokenType::Else)
+            || self.is_at_end()
+        {
+            Expression::Void
+        } else {
+            self.expression()?
+        };
+
+        Ok(StatementKind::Return(value))
     }
 
-    pub fn sell_statement(&mut self) -> Statement {
+    pub fn gamble_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
-        let stock = self.expression();
-        let amount = self.expression();
+        let value = self.expression()?;
 
-        Statement::Sell(stock, amount)
+        Ok(StatementKind::Gamble(value))
     }
 
-    pub fn loan_statement(&mut self) -> Statement {
+    pub fn buy_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
-        let amount = self.expression();
+        let stock = self.expression()?;
+        let amount = self.expression()?;
 
-        Statement::Loan(amount)
+        Ok(StatementKind::Buy(stock, amount))
     }
 
-    pub fn pay_statement(&mut self) -> Statement {
+    pub fn sell_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
-        let amount = self.expression();
+        let stock = self.expression()?;
+        let amount = self.expression()?;
 
-        Statement::Pay(amount)
+        Ok(StatementKind::Sell(stock, amount))
     }
 
-    pub fn work_statement(&mut self) -> Statement {
+    pub fn loan_statement(&mut self) -> Result<StatementKind, MonopolangError> {
         self.advance();
+        let amount = self.expression()?;
 
-        Statement::Work
+        Ok(StatementKind::Loan(amount))
     }
 
-    pub fn block(&mut self) -> Vec<Statement> {
+    pub fn pay_statement(&mut self) -> Result<StatementKind, MonopolangError> {
+        self.advance();
+        let amount = self.expression()?;
+
+        Ok(StatementKind::Pay(amount))
+    }
+
+    pub fn work_statement(&mut self) -> Result<StatementKind, MonopolangError> {
+        self.advance();
+
+        Ok(StatementKind::Work)
+    }
+
+    // Parses a block with the given block parser and wraps it in a block statement
+    fn block_statement(
+        &mut self,
+        block: fn(&mut Self) -> Result<Vec<Statement>, MonopolangError>,
+    ) -> Result<Statement, MonopolangError> {
+        let start = self.current;
+        let statements = block(self)?;
+
+        Ok(Statement::new(
+            StatementKind::Block(statements),
+            self.span_from(start),
+        ))
+    }
+
+    pub fn block(&mut self) -> Result<Vec<Statement>, MonopolangError> {
         let mut statements = Vec::new();
 
         while !self.check(TokenType::End) && !self.is_at_end() {
-            statements.push(self.statement());
+            statements.push(self.statement()?);
         }
 
-        self.consume(TokenType::End, "Expected 'end' after block");
+        self.consume(TokenType::End, "Expected 'end' after block")?;
 
-        statements
+        Ok(statements)
     }
 
-    pub fn if_block(&mut self) -> Vec<Statement> {
+    pub fn if_block(&mut self) -> Result<Vec<Statement>, MonopolangError> {
         let mut statements = Vec::new();
 
-        while !self.check(TokenType::End) && !self.check(TokenType::Else) && !self.is_at_end() {
-            statements.push(self.statement());
+        while !self.check(TokenType::End)
+            && !self.check(TokenType::Elif)
+            && !self.check(TokenType::Else)
+            && !self.is_at_end()
+        {
+            statements.push(self.statement()?);
         }
 
-        if !self.check(TokenType::Else) && !self.check(TokenType::End) {
-            self.error("Expected 'else' or 'end' after if block");
+        if !self.check(TokenType::Elif)
+            && !self.check(TokenType::Else)
+            && !self.check(TokenType::End)
+        {
+            return Err(self.error("Expected 'elif', 'else' or 'end' after if block"));
         }
 
         self.advance();
 
-        statements
+        Ok(statements)
     }
 
-    pub fn expression(&mut self) -> Expression {
+    pub fn expression(&mut self) -> Result<Expression, MonopolangError> {
         self.or_expression()
     }
 
-    pub fn or_expression(&mut self) -> Expression {
-        let mut expr = self.and_expression();
+    pub fn or_expression(&mut self) -> Result<Expression, MonopolangError> {
+        let mut expr = self.and_expression()?;
 
         while self.match_token(TokenType::Or) {
             let operator = self.previous().kind;
-            let right = self.and_expression();
+            let right = self.and_expression()?;
             expr = Expression::Logical(
                 LogicalOperator::from_tokentype(operator),
                 Box::new(expr),
@@ -244,15 +434,15 @@ impl Parser {
             );
         }
 
-        expr
+        Ok(expr)
     }
 
-    pub fn and_expression(&mut self) -> Expression {
-        let mut expr = self.equality();
+    pub fn and_expression(&mut self) -> Result<Expression, MonopolangError> {
+        let mut expr = self.equality()?;
 
         while self.match_token(TokenType::And) {
             let operator = self.previous().kind;
-            let right = self.equality();
+            let right = self.equality()?;
             expr = Expression::Logical(
                 LogicalOperator::from_tokentype(operator),
                 Box::new(expr),
@@ -260,15 +450,15 @@ impl Parser {
             );
         }
 
-        expr
+        Ok(expr)
     }
 
-    pub fn equality(&mut self) -> Expression {
-        let mut expr = self.comparison();
+    pub fn equality(&mut self) -> Result<Expression, MonopolangError> {
+        let mut expr = self.comparison()?;
 
         while self.match_token(TokenType::Equal) || self.match_token(TokenType::BangEqual) {
             let operator = self.previous().kind;
-            let right = self.comparison();
+            let right = self.comparison()?;
             expr = Expression::Binary(
                 BinaryOperator::from_tokentype(operator),
                 Box::new(expr),
@@ -276,11 +466,11 @@ impl Parser {
             );
         }
 
-        expr
+        Ok(expr)
     }
 
-    pub fn comparison(&mut self) -> Expression {
-        let mut expr = self.term();
+    pub fn comparison(&mut self) -> Result<Expression, MonopolangError> {
+        let mut expr = self.bitwise_or()?;
 
         while self.match_token(TokenType::Greater)
             || self.match_token(TokenType::GreaterEqual)
@@ -288,7 +478,48 @@ impl Parser {
             || self.match_token(TokenType::LessEqual)
         {
             let operator = self.previous().kind;
-            let right = self.term();
+            let right = self.bitwise_or()?;
+            expr = Expression::Binary(
+                BinaryOperator::from_tokentype(operator),
+                Box::new(expr),
+                Box::new(right),
+            );
+        }
+
+        Ok(expr)
+    }
+
+    // Bitwise operators bind looser than arithmetic, so 1 + 2 & 3 is (1 + 2) & 3
+    pub fn bitwise_or(&mut self) -> Result<Expression, MonopolangError> {
+        self.binary_level(Self::bitwise_xor, &[TokenType::Pipe])
+    }
+
+    pub fn bitwise_xor(&mut self) -> Result<Expression, MonopolangError> {
+        self.binary_level(Self::bitwise_and, &[TokenType::Caret])
+    }
+
+    pub fn bitwise_and(&mut self) -> Result<Expression, MonopolangError> {
+        self.binary_level(Self::shift, &[TokenType::Ampersand])
+    }
+
+    pub fn shift(&mut self) -> Result<Expression, MonopolangError> {
+        self.binary_level(
+            Self::term,
+            &[TokenType::LessLess, TokenType::GreaterGreater],
+        )
+    }
+
+    // Parses a left associative chain of the given operators between operands
+    fn binary_level(
+        &mut self,
+        operand: fn(&mut Self) -> Result<Expression, MonopolangError>,
+        operators: &[TokenType],
+    ) -> Result<Expression, MonopolangError> {
+        let mut expr = operand(self)?;
+
+        while operators.iter().any(|&kind| self.match_token(kind)) {
+            let operator = self.previous().kind;
+            let right = operand(self)?;
             expr = Expression::Binary(
                 BinaryOperator::from_tokentype(operator),
                 Box::new(expr),
@@ -296,15 +527,15 @@ impl Parser {
             );
         }
 
-        expr
+        Ok(expr)
     }
 
-    pub fn term(&mut self) -> Expression {
-        let mut expr = self.factor();
+    pub fn term(&mut self) -> Result<Expression, MonopolangError> {
+        let mut expr = self.factor()?;
 
         while self.match_token(TokenType::Minus) || self.match_token(TokenType::Plus) {
             let operator = self.previous().kind;
-            let right = self.factor();
+            let right = self.factor()?;
             expr = Expression::Binary(
                 BinaryOperator::from_tokentype(operator),
                 Box::new(expr),
@@ -312,15 +543,19 @@ impl Parser {
             );
         }
 
-        expr
+        Ok(expr)
     }
 
-    pub fn factor(&mut self) -> Expression {
-        let mut expr = self.unary();
+    pub fn factor(&mut self) -> Result<Expression, MonopolangError> {
+        let mut expr = self.unary()?;
 
-        while self.match_token(TokenType::Slash) || self.match_token(TokenType::Star) {
+        while self.match_token(TokenType::Slash)
+            || self.match_token(TokenType::Star)
+            || self.match_token(TokenType::Percent)
+            || self.match_token(TokenType::Div)
+        {
             let operator = self.previous().kind;
-            let right = self.unary();
+            let right = self.unary()?;
             expr = Expression::Binary(
                 BinaryOperator::from_tokentype(operator),
                 Box::new(expr),
@@ -328,46 +563,224 @@ impl Parser {
             );
         }
 
-        expr
+        Ok(expr)
     }
 
-    pub fn unary(&mut self) -> Expression {
+    pub fn unary(&mut self) -> Result<Expression, MonopolangError> {
         if self.match_token(TokenType::Bang) || self.match_token(TokenType::Minus) {
             let operator = self.previous().kind;
-            let right = self.unary();
-            Expression::Unary(UnaryOperator::from_tokentype(operator), Box::new(right))
+            let right = self.unary()?;
+            Ok(Expression::Unary(
+                UnaryOperator::from_tokentype(operator),
+                Box::new(right),
+            ))
         } else {
-            self.primary()
+            self.power()
         }
     }
 
-    pub fn primary(&mut self) -> Expression {
+    // Exponentiation is right associative and binds tighter than unary minus, so -2 ** 2 is -4
+    pub fn power(&mut self) -> Result<Expression, MonopolangError> {
+        let base = self.index()?;
+
+        if self.match_token(TokenType::StarStar) {
+            let exponent = self.unary()?;
+            return Ok(Expression::Binary(
+                BinaryOperator::Power,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+
+        Ok(base)
+    }
+
+    pub fn index(&mut self) -> Result<Expression, MonopolangError> {
+        let mut expr = self.primary()?;
+
+        while self.match_token(TokenType::LeftBracket) {
+            let index = self.expression()?;
+            self.consume(TokenType::RightBracket, "Expected ']' after index")?;
+            expr = Expression::Index(Box::new(expr), Box::new(index));
+        }
+
+        Ok(expr)
+    }
+
+    pub fn primary(&mut self) -> Result<Expression, MonopolangError> {
         if self.match_token(TokenType::False) {
-            Expression::Boolean(false)
+            Ok(Expression::Boolean(false))
         } else if self.match_token(TokenType::True) {
-            Expression::Boolean(true)
+            Ok(Expression::Boolean(true))
         } else if self.match_token(TokenType::Number) {
-            Expression::Number(self.previous().lexeme.parse().unwrap())
+            Ok(Expression::Number(self.previous().lexeme.parse().unwrap()))
         } else if self.match_token(TokenType::String) {
-            Expression::String(self.previous().lexeme.clone())
+            Ok(Expression::String(self.previous().lexeme.clone()))
+        } else if self.match_token(TokenType::Input) {
+            Ok(Expression::Input(false))
+        } else if self.match_token(TokenType::InputNumber) {
+            Ok(Expression::Input(true))
         } else if self.match_token(TokenType::Identifier) {
-            Expression::Variable(self.previous().lexeme.clone())
+            let name = self.previous().lexeme.clone();
+
+            if self.check(TokenType::Dot) {
+                let name = self.qualified_name(name)?;
+                self.consume(
+                    TokenType::LeftParen,
+                    "Expected '(' after procedure name, only procedures can be used from a module",
+                )?;
+                Ok(Expression::Call(name, self.arguments()?))
+            } else if self.match_token(TokenType::LeftParen) {
+                Ok(Expression::Call(name, self.arguments()?))
+            } else {
+                Ok(Expression::Variable(name))
+            }
         } else if self.match_token(TokenType::At) {
             let name = self
-                .consume(TokenType::Identifier, "Expected identifier after '@'")
+                .consume(TokenType::Identifier, "Expected identifier after '@'")?
                 .lexeme;
-            Expression::ReadonlyVariable("@".to_string() + &name)
+            Ok(Expression::ReadonlyVariable("@".to_string() + &name))
         } else if self.match_token(TokenType::Dollar) {
             let name = self
-                .consume(TokenType::Identifier, "Expected identifier after '$'")
+                .consume(TokenType::Identifier, "Expected identifier after '$'")?
                 .lexeme;
-            Expression::StockPrice(name)
+            Ok(Expression::StockPrice(name))
+        } else if self.match_token(TokenType::LeftBracket) {
+            Ok(Expression::List(self.expression_list(
+                TokenType::RightBracket,
+                "Expected ']' after list elements",
+            )?))
+        } else if self.match_token(TokenType::InterpolationStart) {
+            self.interpolation()
+        } else if self.match_token(TokenType::LeftBrace) {
+            self.map()
         } else if self.match_token(TokenType::LeftParen) {
-            let expr = self.expression();
-            self.consume(TokenType::RightParen, "Expected ')' after expression");
-            expr
+            let expr = self.expression()?;
+            self.consume(TokenType::RightParen, "Expected ')' after expression")?;
+            Ok(expr)
         } else {
-            self.error("Expected expression");
+            Err(self.error("Expected expression"))
+        }
+    }
+
+    // Parses the entries of a map literal, assumes the opening '{' has been consumed
+    pub fn map(&mut self) -> Result<Expression, MonopolangError> {
+        let mut entries = Vec::new();
+
+        if !self.check(TokenType::RightBrace) {
+            loop {
+                let key = self.expression()?;
+                self.consume(TokenType::Colon, "Expected ':' after map key")?;
+                entries.push((key, self.expression()?));
+
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after map entries")?;
+
+        Ok(Expression::Map(entries))
+    }
+
+    // Parses the parts of an interpolated string, assumes InterpolationStart has been consumed
+    pub fn interpolation(&mut self) -> Result<Expression, MonopolangError> {
+        let mut parts = Vec::new();
+
+        while !self.match_token(TokenType::InterpolationEnd) {
+            if self.match_token(TokenType::String) {
+                parts.push(InterpolationPart::Literal(self.previous().lexeme.clone()));
+                continue;
+            }
+
+            self.consume(TokenType::LeftBrace, "Expected '{' in string")?;
+            let expression = self.expression()?;
+
+            let decimals = if self.match_token(TokenType::FormatSpec) {
+                Some(self.format_spec()?)
+            } else {
+                None
+            };
+
+            self.consume(
+                TokenType::RightBrace,
+                "Expected '}' after expression in string",
+            )?;
+            parts.push(InterpolationPart::Expression(expression, decimals));
+        }
+
+        Ok(Expression::Interpolation(parts))
+    }
+
+    // Only fixed decimals are supported, '.2' prints numbers like currency
+    fn format_spec(&self) -> Result<usize, MonopolangError> {
+        let spec = &self.previous().lexeme;
+
+        match spec.strip_prefix('.').map(str::parse::<usize>) {
+            Some(Ok(decimals)) if decimals <= 20 => Ok(decimals),
+            _ => Err(self.error_at_previous(&format!(
+                "Invalid format spec ':{}', expected a number of decimals like ':.2'",
+                spec
+            ))),
+        }
+    }
+
+    // Parses the rest of a procedure name like helpers.safe_work, assumes the module name has been consumed
+    fn qualified_name(&mut self, module: String) -> Result<String, MonopolangError> {
+        if !self.match_token(TokenType::Dot) {
+            return Ok(module);
+        }
+
+        let name = self
+            .consume(TokenType::Identifier, "Expected procedure name after '.'")?
+            .lexeme;
+
+        Ok(format!("{}.{}", module, name))
+    }
+
+    // Parses a comma separated argument list, assumes the opening '(' has been consumed
+    pub fn arguments(&mut self) -> Result<Vec<Expression>, MonopolangError> {
+        self.expression_list(TokenType::RightParen, "Expected ')' after arguments")
+    }
+
+    // Parses comma separated expressions up to and including the closing token
+    fn expression_list(
+        &mut self,
+        closing: TokenType,
+        message: &str,
+    ) -> Result<Vec<Expression>, MonopolangError> {
+        let mut expressions = Vec::new();
+
+        if !self.check(closing) {
+            loop {
+                expressions.push(self.expression()?);
+
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(closing, message)?;
+
+        Ok(expressions)
+    }
+
+    // Span from the token at index start to the last consumed token on the same line
+    fn span_from(&self, start: usize) -> Span {
+        let first = &self.tokens[start];
+        let last = self.tokens[start..self.current.max(start + 1)]
+            .iter()
+            .take_while(|token| token.line == first.line)
+            .last()
+            .unwrap_or(first);
+
+        Span {
+            line: first.line,
+            column: first.column,
+            length: (last.column + last.span().length).saturating_sub(first.column),
+            file: first.file.clone(),
         }
     }
 
@@ -383,11 +796,11 @@ impl Parser {
         }
     }
 
-    pub fn consume(&mut self, kind: TokenType, message: &str) -> Token {
+    pub fn consume(&mut self, kind: TokenType, message: &str) -> Result<Token, MonopolangError> {
         if self.check(kind) {
-            self.advance()
+            Ok(self.advance())
         } else {
-            self.error(message);
+            Err(self.error(message))
         }
     }
 
@@ -416,16 +829,26 @@ impl Parser {
         self.tokens[self.current].kind == TokenType::Eof
     }
 
-    pub fn error(&self, message: &str) -> ! {
+    pub fn error(&self, message: &str) -> MonopolangError {
         let token = &self.tokens[self.current];
 
         if token.kind == TokenType::Eof {
-            panic!("Error at end: {}", message);
+            MonopolangError::Parse {
+                message: format!("{} at end of file", message),
+                span: token.span(),
+            }
         } else {
-            panic!(
-                "Error at <{}:{}> | '{}': {}",
-                token.line, token.column, token.lexeme, message
-            );
+            MonopolangError::Parse {
+                message: format!("{}, found '{}'", message, token.lexeme),
+                span: token.span(),
+            }
+        }
+    }
+
+    fn error_at_previous(&self, message: &str) -> MonopolangError {
+        MonopolangError::Parse {
+            message: message.to_string(),
+            span: self.previous().span(),
         }
     }
 }
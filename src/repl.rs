@@ -0,0 +1,182 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    compiler::Compiler,
+    economy::Economy,
+    error::MonopolangError,
+    lexer::{Lexer, TokenType},
+    parser::Parser,
+    sandbox::Sandbox,
+    trace::Tracer,
+    vm::VM,
+};
+
+const HELP: &str = "Enter statements or procedures to run them, blocks continue until their 'end'
+
+Commands:
+  :balance    Show the balance and debt
+  :portfolio  Show the stocks you own
+  :ops        Show the number of operations executed
+  :summary    Show everything above
+  :help       Show this message
+  :quit       Leave the REPL (or press Ctrl-D)";
+
+// Every input is compiled into the same VM, so globals, procedures and the economy
+// carry over from one input to the next
+pub struct Repl {
+    compiler: Compiler,
+    history: String, // Every line entered so far, so errors can show earlier inputs
+    lines: u32,
+    seed: u64,
+}
+
+impl Repl {
+    pub fn new(economy: Economy, seed: u64, tracer: Option<Tracer>) -> Self {
+        let mut vm = VM::new(economy, seed);
+        vm.tracer = tracer;
+
+        Repl {
+            compiler: Compiler::new(Vec::new(), vm),
+            history: String::new(),
+            lines: 0,
+            seed,
+        }
+    }
+
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.compiler.vm.sandbox = Some(sandbox);
+        self
+    }
+
+    // Reads inputs until the end of input or :quit. The program shares both handles with the REPL:
+    // it reads from the same input and prints to the same output, which also gets prompts and errors
+    pub fn run(&mut self, input: impl BufRead + 'static, output: impl Write + 'static) {
+        self.compiler.vm.input = Box::new(input);
+        self.compiler.vm.output = Box::new(output);
+
+        let _ = writeln!(
+            self.compiler.vm.output,
+            "monopolang repl (seed {}), type :help for a list of commands",
+            self.seed
+        );
+
+        let mut input = String::new();
+
+        loop {
+            let prompt = if input.is_empty() { "> " } else { "... " };
+            let _ = write!(self.compiler.vm.output, "{}", prompt);
+            let _ = self.compiler.vm.output.flush();
+
+            // Programs read input from the same reader, so a line they read is never taken for a statement
+            let mut line = String::new();
+            match self.compiler.vm.input.read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => (),
+            }
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if input.is_empty() {
+                match line.trim() {
+                    "" => continue,
+                    command if command.starts_with(':') => {
+                        if !self.command(command) {
+                            break;
+                        }
+                        continue;
+                    }
+                    _ => (),
+                }
+            }
+
+            input.push_str(line);
+            input.push('\n');
+
+            if !is_complete(&input) {
+                continue;
+            }
+
+            if let Err(err) = self.eval(&input) {
+                let rendered = err.render("<repl>", &self.history);
+                let _ = write!(self.compiler.vm.output, "{}", rendered);
+            }
+
+            input.clear();
+        }
+
+        let _ = writeln!(self.compiler.vm.output);
+        let _ = self.compiler.vm.output.flush();
+    }
+
+    // Runs a meta-command, returns false when the REPL should stop
+    fn command(&mut self, command: &str) -> bool {
+        let summary = self.compiler.vm.summary();
+
+        let text = match command {
+            ":balance" => format!(
+                "balance: ${:.2}\ndebt: ${:.2}",
+                summary.balance, summary.debt
+            ),
+            ":portfolio" if summary.portfolio.is_empty() => "You don't own any stocks".to_string(),
+            ":portfolio" => summary
+                .portfolio
+                .iter()
+                .map(|holding| holding.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ":ops" => format!("operations: {}", summary.operations),
+            ":summary" => summary.to_string(),
+            ":help" => HELP.to_string(),
+            ":quit" | ":q" => return false,
+            _ => format!(
+                "Unknown command '{}', type :help for a list of commands",
+                command
+            ),
+        };
+
+        let _ = writeln!(self.compiler.vm.output, "{}", text);
+        true
+    }
+
+    fn eval(&mut self, input: &str) -> Result<(), MonopolangError> {
+        let first_line = self.lines + 1;
+
+        // Inputs that fail still take up their lines, so spans of later inputs keep pointing at the right line
+        self.history.push_str(input);
+        self.lines += input.lines().count() as u32;
+
+        let tokens = Lexer::new(input.to_string())
+            .starting_at_line(first_line)
+            .scan_tokens()?;
+        let ast = Parser::new(tokens).parse()?;
+
+        self.compiler.compile_more(ast)?;
+
+        if let Err(err) = self.compiler.vm.execute() {
+            self.compiler.vm.recover();
+            return Err(err);
+        }
+
+        Ok(())
+    }
+}
+
+// Input is complete once every block opened with 'do' or 'then' has been closed with 'end'
+// An 'elif' closes its branch and its 'then' opens the next one
+// Input that doesn't lex is complete too, so the error is reported straight away
+fn is_complete(input: &str) -> bool {
+    let tokens = match Lexer::new(input.to_string()).scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(_) => return true,
+    };
+
+    let depth: i32 = tokens
+        .iter()
+        .map(|token| match token.kind {
+            TokenType::Do | TokenType::Then => 1,
+            TokenType::End | TokenType::Elif => -1,
+            _ => 0,
+        })
+        .sum();
+
+    depth <= 0
+}
@@ -0,0 +1,82 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+};
+
+// Directory the file functions are confined to, paths in programs are relative to it
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    root: PathBuf, // Canonical, so resolved paths can be compared against it
+}
+
+impl Sandbox {
+    pub fn new(directory: impl AsRef<Path>) -> io::Result<Self> {
+        let root = directory.as_ref().canonicalize()?;
+
+        if !root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a directory",
+            ));
+        }
+
+        Ok(Sandbox { root })
+    }
+
+    pub fn read(&self, path: &str) -> Result<String, String> {
+        let file = self.resolve(path)?;
+
+        std::fs::read_to_string(&file).map_err(|err| format!("Failed to read '{}': {}", path, err))
+    }
+
+    // Creates the file if needed, the directory it is in has to exist already
+    pub fn write(&self, path: &str, contents: &str, append: bool) -> Result<(), String> {
+        let file = self.resolve(path)?;
+
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&file)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|err| format!("Failed to write '{}': {}", path, err))
+    }
+
+    // Only plain relative paths are allowed, and symlinks may not lead out of the sandbox either
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let relative = Path::new(path);
+        let plain = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+        if !plain || path.is_empty() {
+            return Err(format!("Path '{}' is outside of the sandbox", path));
+        }
+
+        let file = self.root.join(relative);
+        let (parent, name) = match (file.parent(), file.file_name()) {
+            (Some(parent), Some(name)) => (parent, name),
+            _ => return Err(format!("Path '{}' is not a file", path)),
+        };
+
+        let parent = parent
+            .canonicalize()
+            .map_err(|err| format!("Failed to open '{}': {}", path, err))?;
+        let file = match parent.join(name).canonicalize() {
+            Ok(file) => file,
+            // A broken symlink could still point anywhere once the file is created
+            Err(_) if parent.join(name).symlink_metadata().is_ok() => {
+                return Err(format!("Path '{}' is outside of the sandbox", path))
+            }
+            Err(_) => parent.join(name), // Doesn't exist yet, only the directory can be checked
+        };
+
+        if !file.starts_with(&self.root) {
+            return Err(format!("Path '{}' is outside of the sandbox", path));
+        }
+
+        Ok(file)
+    }
+}
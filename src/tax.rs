@@ -0,0 +1,81 @@
+use crate::economy::Taxes;
+
+// Levies taxes on gains in net worth (balance - debt) at the end of every tax period
+// Losses are carried forward and deducted from the gains of later periods
+#[derive(Debug, Clone)]
+pub struct TaxAuthority {
+    period_start: f64, // Net worth at the start of the current period
+    carried_loss: f64, // Losses from earlier periods that have not been offset yet
+    timer: u32,        // Operations into the current period
+    pub paid: f64,     // Total tax paid so far
+}
+
+impl TaxAuthority {
+    pub fn new(net_worth: f64) -> Self {
+        TaxAuthority {
+            period_start: net_worth,
+            carried_loss: 0.0,
+            timer: 0,
+            paid: 0.0,
+        }
+    }
+
+    // Tax due if the period ended with the given net worth
+    pub fn owed(&self, net_worth: f64, taxes: &Taxes) -> f64 {
+        let taxable = net_worth - self.period_start - self.carried_loss;
+        taxable.max(0.0) * taxes.rate
+    }
+
+    pub fn operations_until_due(&self, taxes: &Taxes) -> u32 {
+        taxes.interval - self.timer
+    }
+
+    // Advances the period by one operation, returns the tax to levy when the period ends
+    pub fn tick(&mut self, net_worth: f64, taxes: &Taxes) -> Option<f64> {
+        self.timer += 1;
+
+        if self.timer < taxes.interval {
+            return None;
+        }
+
+        self.timer = 0;
+
+        let tax = self.owed(net_worth, taxes);
+        let gain = net_worth - self.period_start;
+
+        // Gains first use up carried losses, new losses are added to them
+        self.carried_loss = (self.carried_loss - gain).max(0.0);
+        self.period_start = net_worth - tax;
+        self.paid += tax;
+
+        Some(tax)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn losses_are_carried_forward_into_later_gains() {
+        let taxes = Taxes {
+            interval: 2,
+            rate: 0.5,
+        };
+        let mut authority = TaxAuthority::new(100.0);
+
+        // A period that loses 40 pays nothing
+        assert_eq!(authority.tick(90.0, &taxes), None);
+        assert_eq!(authority.tick(60.0, &taxes), Some(0.0));
+
+        // Gaining 100 afterwards is only taxed on the 60 above the carried loss
+        assert_eq!(authority.tick(100.0, &taxes), None);
+        assert_eq!(authority.owed(160.0, &taxes), 30.0);
+        assert_eq!(authority.tick(160.0, &taxes), Some(30.0));
+
+        // The loss is used up, the next gain is taxed in full
+        assert_eq!(authority.tick(130.0, &taxes), None);
+        assert_eq!(authority.tick(150.0, &taxes), Some(10.0));
+        assert_eq!(authority.paid, 40.0);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{fmt, io::Write};
+
+use crate::{value::Value, vm::OpCode};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceFilter {
+    Economy,           // Only economy opcodes (costs, loans, trades, ...)
+    Balance,           // Only instructions that changed the balance
+    Procedure(String), // Only instructions executed directly inside the named procedure
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    Text, // Human readable, one instruction per line
+    Json, // One JSON object per line
+}
+
+// Everything known about a single executed instruction
+pub struct TraceEntry<'a> {
+    pub ip: usize,
+    pub op: &'a OpCode,
+    pub procedure: Option<&'a str>, // None when executing the main program
+    pub balance_before: f64,
+    pub balance: f64,
+    pub debt: f64,
+    pub stack: &'a [Value],
+}
+
+pub struct Tracer {
+    filters: Vec<TraceFilter>,
+    format: TraceFormat,
+    output: Box<dyn Write>,
+}
+
+impl Tracer {
+    pub fn new(filters: Vec<TraceFilter>, format: TraceFormat, output: Box<dyn Write>) -> Self {
+        Tracer {
+            filters,
+            format,
+            output,
+        }
+    }
+
+    // An entry is only recorded if it passes every filter
+    fn accepts(&self, entry: &TraceEntry) -> bool {
+        self.filters.iter().all(|filter| match filter {
+            TraceFilter::Economy => entry.op.is_economy(),
+            TraceFilter::Balance => entry.balance != entry.balance_before,
+            TraceFilter::Procedure(name) => entry.procedure == Some(name.as_str()),
+        })
+    }
+
+    pub fn record(&mut self, entry: &TraceEntry) -> std::io::Result<()> {
+        if !self.accepts(entry) {
+            return Ok(());
+        }
+
+        match self.format {
+            TraceFormat::Text => writeln!(
+                self.output,
+                "ip: {}, procedure: {}, instruction: {:?}, balance: {:?}, stack: {:?}",
+                entry.ip,
+                entry.procedure.unwrap_or("<main>"),
+                entry.op,
+                entry.balance,
+                entry.stack
+            ),
+            TraceFormat::Json => {
+                let stack: Vec<String> = entry
+                    .stack
+                    .iter()
+                    .map(|value| json_string(&value.format()))
+                    .collect();
+
+                writeln!(
+                    self.output,
+                    "{{\"ip\":{},\"op\":{},\"procedure\":{},\"balance_before\":{},\"balance\":{},\"debt\":{},\"stack\":[{}]}}",
+                    entry.ip,
+                    json_string(&format!("{:?}", entry.op)),
+                    entry.procedure.map_or("null".to_string(), json_string),
+                    json_number(entry.balance_before),
+                    json_number(entry.balance),
+                    json_number(entry.debt),
+                    stack.join(",")
+                )
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("filters", &self.filters)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl TraceFilter {
+    // Parses a filter as given on the command line: economy, balance or proc=<name>
+    pub fn parse(filter: &str) -> Option<Self> {
+        match filter {
+            "economy" => Some(TraceFilter::Economy),
+            "balance" => Some(TraceFilter::Balance),
+            _ => filter
+                .strip_prefix("proc=")
+                .filter(|name| !name.is_empty())
+                .map(|name| TraceFilter::Procedure(name.to_string())),
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+// JSON has no representation for NaN or infinity
+fn json_number(n: f64) -> String {
+    if n.is_finite() {
+        n.to_string()
+    } else {
+        "null".to_string()
+    }
+}
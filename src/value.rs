@@ -1,9 +1,13 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Number(f64),
     Boolean(bool),
     Void,
     String(String),
+    List(Rc<RefCell<Vec<Value>>>), // Shared, so changes through one variable show up in every other
+    Map(Rc<RefCell<BTreeMap<String, Value>>>), // Shared like lists, keys are kept in order
 }
 
 impl Value {
@@ -13,6 +17,19 @@ impl Value {
             Value::Boolean(b) => *b,
             Value::Void => false,
             Value::String(_) => false,
+            Value::List(items) => !items.borrow().is_empty(),
+            Value::Map(entries) => !entries.borrow().is_empty(),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::Void => "void",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
         }
     }
 
@@ -22,6 +39,46 @@ impl Value {
             Value::Boolean(b) => b.to_string(),
             Value::Void => "void".to_string(),
             Value::String(s) => s.clone(),
+            Value::List(items) => {
+                let items: Vec<String> = items.borrow().iter().map(Value::format_nested).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Value::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| format!("\"{}\": {}", key, value.format_nested()))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+        }
+    }
+
+    // Whether this value is the given list or map, or holds it somewhere inside
+    // Storing such a value in the collection is refused, so collections never contain themselves
+    // and printing or comparing them always ends
+    pub fn reaches(&self, collection: &Value) -> bool {
+        let same = match (self, collection) {
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        };
+
+        same || match self {
+            Value::List(items) => items.borrow().iter().any(|item| item.reaches(collection)),
+            Value::Map(entries) => entries
+                .borrow()
+                .values()
+                .any(|value| value.reaches(collection)),
+            _ => false,
+        }
+    }
+
+    // Strings inside lists and maps are quoted, so ["1"] and [1] look different
+    fn format_nested(&self) -> String {
+        match self {
+            Value::String(s) => format!("\"{}\"", s),
+            value => value.format(),
         }
     }
 
@@ -36,4 +93,12 @@ impl Value {
     pub fn from_boolean(b: bool) -> Value {
         Value::Boolean(b)
     }
+
+    pub fn from_list(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn from_map(entries: BTreeMap<String, Value>) -> Value {
+        Value::Map(Rc::new(RefCell::new(entries)))
+    }
 }
@@ -1,6 +1,23 @@
-use std::collections::HashMap;
-
-use crate::value::Value;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    io::{BufRead, Write},
+};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    builtins::{self, Builtin},
+    economy::Economy,
+    error::MonopolangError,
+    lexer::Span,
+    sandbox::Sandbox,
+    tax::TaxAuthority,
+    trace::{TraceEntry, Tracer},
+    value::Value,
+};
 
 #[derive(Debug, Clone)]
 pub enum OpCode {
@@ -8,10 +25,21 @@ pub enum OpCode {
     Print,
     GetGlobal(String),
     SetGlobal(String),
+    DefineConstant(String), // Sets a global that can't be assigned again
+    GetLocal(usize),        // Stack slot relative to the current frame
+    SetLocal(usize),
     Add,
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    IntegerDivide,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
     Negate,
     Not,
     Equal,
@@ -20,13 +48,23 @@ pub enum OpCode {
     GreaterEqual,
     Less,
     LessEqual,
-    And,
-    Or,
     JumpIfFalse(usize),
-    JumpForwardIfFalse(isize),
+    JumpIfFalseKeep(usize), // Like JumpIfFalse, but the condition stays on the stack when jumping
+    JumpIfTrueKeep(usize),
     Jump(usize),
-    JumpForward(isize),
-    ProcedureCall(String),
+    Call(String, usize),     // Name, Argument count
+    Builtin(Builtin, usize), // Builtin, Argument count
+    BuildList(usize),        // Element count
+    BuildMap(usize),         // Entry count
+    FormatDecimals(usize),   // Number of decimals, turns a number into a string for interpolation
+    Join(usize),             // Part count, joins the parts of an interpolated string
+    Input,                   // Reads a line without its line ending
+    InputNumber,             // Reads a line and parses it as a number
+    GetIndex,
+    Iterate,        // Replaces a collection with a list of the items to loop over
+    ForNext(usize), // Pushes the next item, or jumps to the offset when there are none left
+    SetIndex,
+    Return,
     Pop,
 
     // Economy System
@@ -40,50 +78,140 @@ pub enum OpCode {
     GetStockPrice(String),
 }
 
+impl OpCode {
+    pub fn is_economy(&self) -> bool {
+        matches!(
+            self,
+            OpCode::Cost(_)
+                | OpCode::Gamble
+                | OpCode::Loan
+                | OpCode::Repay
+                | OpCode::Work
+                | OpCode::Buy
+                | OpCode::Sell
+                | OpCode::GetStockPrice(_)
+        )
+    }
+}
+
+// Share amounts closer than this are treated as equal, to absorb floating point error
+const SHARE_EPSILON: f64 = 1e-9;
+
+// Maximum procedure call depth before the VM gives up
+const FRAMES_MAX: usize = 4096;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Procedure {
+    address: usize, // Entry address in code
+    arity: usize,
+}
+
+// Rust function registered by the host, gets the arguments in order and returns a value or an error message
+pub type NativeFunction = Box<dyn FnMut(&[Value]) -> Result<Value, String>>;
+
+pub struct Native {
+    arity: usize,
+    price: f64, // Charged on every call, on top of the usual calling costs
+    function: NativeFunction,
+}
+
 #[derive(Debug, Clone)]
+pub struct CallFrame {
+    address: usize, // Entry address of the procedure, its name is only looked up while tracing
+    return_address: usize, // Instruction to resume at once the procedure returns
+    stack_base: usize, // Stack slot of the first argument
+}
+
 pub struct VM {
     pub code: Vec<OpCode>,
+    pub spans: Vec<Span>, // Source location of each op in code, for error reporting
     pub constants: Vec<Value>,
     globals: HashMap<String, Value>,
-    pub procedures: HashMap<String, Vec<OpCode>>,
+    frozen: HashSet<String>, // Globals defined with const, assigning to them fails
+    pub procedures: HashMap<String, Procedure>,
+    natives: HashMap<String, Native>,
+    frames: Vec<CallFrame>,
     stack: Vec<Value>,
     ip: usize,
+    operations: u64, // Instructions executed so far
+    pub tracer: Option<Tracer>,
+    pub output: Box<dyn Write>, // Where print writes to, stdout unless the host says otherwise
+    pub input: Box<dyn BufRead>, // Where input reads from, empty unless the host says otherwise
+    pub sandbox: Option<Sandbox>, // Directory the file functions may use, no file access without one
 
     // Economy System
+    pub economy: Economy,
+    rng: ChaCha8Rng, // All randomness comes from here, a fixed algorithm keeps seeded runs replayable across rand versions
     balance: f64,
     debt: f64,
-    stock_ownership: HashMap<String, u32>,
-    stock_prices: HashMap<String, f64>,
+    // Ordered maps, so stock updates draw random numbers in the same order on every run
+    stock_ownership: BTreeMap<String, f64>, // Shares can be fractional
+    stock_prices: BTreeMap<String, f64>,
     won_last_gamble: bool,
     op_debt_timer: u32,  // Timer for operations, used for forced debt collection
     op_work_timer: u32,  // Timer for operations, used for forced work
     op_stock_timer: u32, // Timer for operations, used for forced stock trading
+    op_inflation_timer: u32, // Timer for operations, used for raising prices
+    price_level: f64,    // Multiplier applied to every cost, grows with inflation
     can_work: bool,
+    taxes: TaxAuthority,
+}
+
+// State of the economy at the end of a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub balance: f64,
+    pub debt: f64,
+    pub taxes_paid: f64,
+    pub operations: u64,
+    pub portfolio: Vec<Holding>, // Sorted by stock name
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holding {
+    pub stock: String,
+    pub shares: f64,
+    pub price: f64, // Current price of a single share
 }
 
 impl VM {
-    pub fn new() -> Self {
+    pub fn new(economy: Economy, seed: u64) -> Self {
         VM {
             code: Vec::new(),
+            spans: Vec::new(),
             constants: Vec::new(),
             globals: HashMap::new(),
+            frozen: HashSet::new(),
             procedures: HashMap::new(),
+            natives: HashMap::new(),
+            frames: Vec::new(),
             stack: Vec::new(),
             ip: 0,
-            balance: 250.0,
+            operations: 0,
+            tracer: None,
+            output: Box::new(std::io::stdout()),
+            input: Box::new(std::io::empty()),
+            sandbox: None,
+            balance: economy.starting_balance,
             debt: 0.0,
-            stock_ownership: HashMap::new(),
-            stock_prices: HashMap::new(),
+            stock_ownership: BTreeMap::new(),
+            stock_prices: BTreeMap::new(),
             won_last_gamble: false,
             op_debt_timer: 0,
             op_work_timer: 0,
             op_stock_timer: 0,
+            op_inflation_timer: 0,
+            price_level: 1.0,
             can_work: true,
+            taxes: TaxAuthority::new(economy.starting_balance),
+            economy,
+            rng: ChaCha8Rng::seed_from_u64(seed),
         }
     }
 
-    pub fn write_op(&mut self, op: OpCode) -> usize {
+    pub fn write_op(&mut self, op: OpCode, span: Span) -> usize {
         self.code.push(op);
+        self.spans.push(span);
         self.code.len() - 1
     }
 
@@ -98,436 +226,1033 @@ impl VM {
         self.constants.len() - 1
     }
 
-    pub fn write_procedure(&mut self, name: String, code: Vec<OpCode>) {
-        self.procedures.insert(name, code);
+    pub fn write_procedure(&mut self, name: String, address: usize, arity: usize) {
+        self.procedures.insert(name, Procedure { address, arity });
+    }
+
+    // Makes a Rust function callable from monopolang like a procedure, procedures with the same name take precedence
+    // Builtins are resolved before natives, so a native can't share a builtin's name
+    pub fn register_native(
+        &mut self,
+        name: impl Into<String>,
+        arity: usize,
+        price: f64,
+        function: NativeFunction,
+    ) -> Result<(), String> {
+        let name = name.into();
+        if Builtin::from_name(&name).is_some() {
+            return Err(format!(
+                "Cannot register native '{}', a builtin has that name",
+                name
+            ));
+        }
+
+        self.natives.insert(
+            name,
+            Native {
+                arity,
+                price,
+                function,
+            },
+        );
+        Ok(())
     }
 
     pub fn read_constant(&self, index: usize) -> Value {
         self.constants[index].clone()
     }
 
-    pub fn create_stock(&mut self, name: &String) {
-        self.stock_prices
-            .insert(name.clone(), rand::random::<f64>() * 100.0);
+    pub fn create_stock(&mut self, name: &str) {
+        self.stock_prices.insert(
+            name.to_string(),
+            self.rng.gen::<f64>() * self.economy.stocks.initial_price,
+        );
+    }
+
+    // Finds the procedure starting at the given address, a scan is fine since only tracing needs it
+    fn procedure_name(&self, address: usize) -> Option<&str> {
+        self.procedures
+            .iter()
+            .find(|(_, procedure)| procedure.address == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            balance: self.balance,
+            debt: self.debt,
+            taxes_paid: self.taxes.paid,
+            operations: self.operations,
+            portfolio: self.portfolio(),
+        }
+    }
+
+    pub fn portfolio(&self) -> Vec<Holding> {
+        self.stock_ownership
+            .iter()
+            .map(|(stock, shares)| Holding {
+                stock: stock.clone(),
+                shares: *shares,
+                price: self.stock_prices[stock],
+            })
+            .collect()
+    }
+
+    // Gets the VM ready for more code after a runtime error, keeping globals, procedures and the economy
+    pub fn recover(&mut self) {
+        self.stack.clear();
+        self.frames.clear();
+        self.ip = self.code.len();
+    }
+
+    // Gets the price of a stock, listing it at a random price if nobody has traded it yet
+    fn stock_price(&mut self, name: &str) -> f64 {
+        if !self.stock_prices.contains_key(name) {
+            self.create_stock(name);
+        }
+
+        self.stock_prices[name]
+    }
+
+    // Pops the stock name and share amount shared by buy and sell
+    fn pop_trade(&mut self) -> Result<(String, f64), MonopolangError> {
+        let name = self.stack.pop().unwrap();
+        let amount = self.stack.pop().unwrap();
+
+        let name = match name {
+            Value::String(name) => name,
+            _ => return Err(self.runtime_error("Stock name must be a string")),
+        };
+
+        match amount {
+            Value::Number(amount) if amount.is_finite() && amount > 0.0 => Ok((name, amount)),
+            Value::Number(_) => Err(self.economic_error("Share amount must be a positive number")),
+            _ => Err(self.runtime_error("Share amount must be a number")),
+        }
+    }
+
+    // Pops the arguments, charges the price and pushes the result of a builtin function
+    fn call_builtin(
+        &mut self,
+        builtin: Builtin,
+        argument_count: usize,
+    ) -> Result<(), MonopolangError> {
+        let arguments = self.stack.split_off(self.stack.len() - argument_count);
+        if builtin.is_file() {
+            return self.call_file_builtin(builtin, &arguments);
+        }
+
+        let cost = builtin.cost(&arguments, &self.economy.costs);
+        self.charge(cost, &format!("call {}", builtin.name()))?;
+
+        let value = builtin
+            .call(&arguments)
+            .map_err(|message| self.runtime_error(&message))?;
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    fn call_file_builtin(
+        &mut self,
+        builtin: Builtin,
+        arguments: &[Value],
+    ) -> Result<(), MonopolangError> {
+        let sandbox = match &self.sandbox {
+            Some(sandbox) => sandbox.clone(),
+            None => {
+                return Err(self.io_error(&format!(
+                    "{} needs file access, run with --sandbox <dir> to allow it",
+                    builtin.name()
+                )))
+            }
+        };
+
+        match (builtin, arguments) {
+            // Reading has no side effects, so the bill can wait until the size is known
+            (Builtin::ReadFile, [Value::String(path)]) => {
+                let contents = sandbox
+                    .read(path)
+                    .map_err(|message| self.io_error(&message))?;
+                self.charge_transfer(builtin, contents.len())?;
+                self.stack.push(Value::String(contents));
+            }
+            // Writes are paid for before anything touches the disk
+            (
+                Builtin::WriteFile | Builtin::AppendFile,
+                [Value::String(path), Value::String(contents)],
+            ) => {
+                self.charge_transfer(builtin, contents.len())?;
+                sandbox
+                    .write(path, contents, builtin == Builtin::AppendFile)
+                    .map_err(|message| self.io_error(&message))?;
+                self.stack.push(Value::Void);
+            }
+            _ => return Err(self.runtime_error(&builtin.type_error(arguments))),
+        }
+
+        Ok(())
+    }
+
+    // Charges the file cost for every started kilobyte
+    fn charge_transfer(&mut self, builtin: Builtin, bytes: usize) -> Result<(), MonopolangError> {
+        let kilobytes = (bytes as f64 / 1024.0).ceil();
+        self.charge(
+            self.economy.costs.file * kilobytes,
+            &format!("{} {} bytes", builtin.name(), bytes),
+        )
+    }
+
+    // Adding an entry costs as much as storing a list element, replacing one is free
+    fn set_entry(
+        &mut self,
+        entries: &RefCell<BTreeMap<String, Value>>,
+        key: &Value,
+        item: Value,
+    ) -> Result<(), MonopolangError> {
+        let key = builtins::key(key).map_err(|message| self.runtime_error(&message))?;
+
+        if !entries.borrow().contains_key(key) {
+            self.charge(
+                self.economy.costs.element,
+                &format!("add '{}' to the map", key),
+            )?;
+        }
+
+        entries.borrow_mut().insert(key.to_string(), item);
+
+        Ok(())
+    }
+
+    // Reads a line of input and charges for every byte of it, including the line ending
+    fn read_line(&mut self) -> Result<String, MonopolangError> {
+        // Prompts printed before the read have to show up first
+        let _ = self.output.flush();
+
+        let mut line = String::new();
+        let bytes = match self.input.read_line(&mut line) {
+            Ok(0) => return Err(self.io_error("Reached the end of input")),
+            Ok(bytes) => bytes,
+            Err(err) => return Err(self.io_error(&format!("Failed to read input: {}", err))),
+        };
+
+        self.charge(
+            self.economy.costs.input * bytes as f64,
+            &format!("read {} bytes of input", bytes),
+        )?;
+
+        let length = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(length);
+
+        Ok(line)
+    }
+
+    // Pops the arguments, charges the price and pushes the result of a native function
+    fn call_native(&mut self, name: &str, argument_count: usize) -> Result<(), MonopolangError> {
+        let native = &self.natives[name];
+        let price = native.price;
+
+        if native.arity != argument_count {
+            return Err(self.runtime_error(&format!(
+                "Native function '{}' expects {} arguments but got {}",
+                name, native.arity, argument_count
+            )));
+        }
+
+        // Paid before running the function, it may have side effects in the host
+        self.charge(price, &format!("call {}", name))?;
+
+        let arguments = self.stack.split_off(self.stack.len() - argument_count);
+        let native = self.natives.get_mut(name).unwrap();
+
+        let value = (native.function)(&arguments)
+            .map_err(|message| self.runtime_error(&format!("{} failed: {}", name, message)))?;
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    // Takes a price, adjusted for inflation, out of the balance, refusing if that would overdraw it
+    // Every charge made outside of Cost ops goes through here, so they all follow the same rules
+    fn charge(&mut self, amount: f64, what: &str) -> Result<(), MonopolangError> {
+        let cost = amount * self.price_level;
+
+        if cost > self.balance {
+            return Err(self.economic_error(&format!(
+                "Insufficient funds to {}, it costs ${}",
+                what, cost
+            )));
+        }
+        self.balance -= cost;
+
+        Ok(())
+    }
+
+    fn pop_numbers(&mut self) -> Result<(f64, f64), MonopolangError> {
+        let b = self.stack.pop().unwrap();
+        let a = self.stack.pop().unwrap();
+
+        match (a, b) {
+            (Value::Number(a), Value::Number(b)) => Ok((a, b)),
+            _ => Err(self.runtime_error("Operands must be numbers")),
+        }
+    }
+
+    // % and div have no sensible result for a zero divisor, unlike / which gives infinity
+    fn pop_divisible(&mut self) -> Result<(f64, f64), MonopolangError> {
+        let (a, b) = self.pop_numbers()?;
+
+        if b == 0.0 {
+            return Err(self.runtime_error("Division by zero"));
+        }
+
+        Ok((a, b))
+    }
+
+    // Bitwise operators work on whole numbers only
+    fn pop_integers(&mut self) -> Result<(i64, i64), MonopolangError> {
+        let (a, b) = self.pop_numbers()?;
+
+        if a.fract() != 0.0 || b.fract() != 0.0 || !a.is_finite() || !b.is_finite() {
+            return Err(self.runtime_error("Bitwise operands must be whole numbers"));
+        }
+
+        Ok((a as i64, b as i64))
+    }
+
+    fn frame_base(&self) -> usize {
+        match self.frames.last() {
+            Some(frame) => frame.stack_base,
+            None => 0,
+        }
+    }
+
+    fn runtime_error(&self, message: &str) -> MonopolangError {
+        MonopolangError::Runtime {
+            message: message.to_string(),
+            span: self.spans[self.ip].clone(),
+        }
+    }
+
+    fn io_error(&self, message: &str) -> MonopolangError {
+        MonopolangError::Io {
+            message: message.to_string(),
+            span: self.spans[self.ip].clone(),
+        }
+    }
+
+    fn economic_error(&self, message: &str) -> MonopolangError {
+        MonopolangError::Economic {
+            message: message.to_string(),
+            span: self.spans[self.ip].clone(),
+        }
     }
 
-    pub fn execute(&mut self) {
+    pub fn execute(&mut self) -> Result<(), MonopolangError> {
         while self.ip < self.code.len() {
             self.op_debt_timer += 1;
-            self.op_debt_timer %= 20000; // Reset timer every 20000 operations
+            self.op_debt_timer %= self.economy.debt_collection.interval;
 
             if self.op_debt_timer == 0 {
-                // Force collection of 5% of debt
-                let debt_collection = self.debt * 0.05;
+                // Force collection of part of the debt
+                let debt_collection = self.debt * self.economy.debt_collection.rate;
                 self.debt -= debt_collection;
                 self.balance -= debt_collection;
             }
 
             self.op_work_timer += 1;
-            self.op_work_timer %= 225; // Allow work every 225 operations
+            self.op_work_timer %= self.economy.work.cooldown; // Allow work again after the cooldown
 
             if self.op_work_timer == 0 {
                 self.can_work = true;
             }
 
             self.op_stock_timer += 1;
-            self.op_stock_timer %= 1000; // Update stock prices every 1000 operations
+            self.op_stock_timer %= self.economy.stocks.tick_interval; // Update stock prices every tick
 
             if self.op_stock_timer == 0 {
+                let volatility = self.economy.stocks.volatility;
+                let drift = self.economy.stocks.drift;
+
                 for (_, price) in self.stock_prices.iter_mut() {
-                    let change = rand::random::<f64>() * 0.1 - 0.05;
-                    *price += change;
+                    let change = self.rng.gen::<f64>() * volatility * 2.0 - volatility + drift;
+                    // Prices can crash, but never below zero
+                    *price = (*price + change).max(0.0);
                 }
             }
 
-            // Print the instruction pointer, instruction, and stack
-            println!(
-                "ip: {}, instruction: {:?}, balance: {:?}, stack: {:?}",
-                self.ip, self.code[self.ip], self.balance, self.stack
-            );
+            self.op_inflation_timer += 1;
+            self.op_inflation_timer %= self.economy.inflation.interval;
 
-            // Print the code
-            // for (i, instruction) in self.code.iter().enumerate() {
-            //     if i == self.ip {
-            //         print!("-> ");
-            //     } else {
-            //         print!("   ");
-            //     }
+            if self.op_inflation_timer == 0 {
+                self.price_level *= 1.0 + self.economy.inflation.rate;
+            }
 
-            //     println!("{:?}", instruction);
-            // }
+            if let Some(tax) = self
+                .taxes
+                .tick(self.balance - self.debt, &self.economy.taxes)
+            {
+                self.balance -= tax;
+            }
 
-            match &self.code[self.ip] {
-                OpCode::Constant(index) => {
-                    self.stack.push(self.read_constant(*index));
+            self.operations += 1;
+
+            let ip = self.ip;
+            let balance_before = self.balance;
+            let procedure = match (&self.tracer, self.frames.last()) {
+                (Some(_), Some(frame)) => Some(frame.address),
+                _ => None,
+            };
+
+            let stepped = self.step();
+
+            // The failing instruction is traced too, so the trace shows where the program stopped
+            if let Some(mut tracer) = self.tracer.take() {
+                let procedure = procedure.and_then(|address| self.procedure_name(address));
+                let result = tracer
+                    .record(&TraceEntry {
+                        ip,
+                        op: &self.code[ip],
+                        procedure,
+                        balance_before,
+                        balance: self.balance,
+                        debt: self.debt,
+                        stack: &self.stack,
+                    })
+                    .and_then(|()| match stepped {
+                        Ok(()) => Ok(()),
+                        Err(_) => tracer.flush(),
+                    });
+                self.tracer = Some(tracer);
+
+                if let (Ok(()), Err(err)) = (&stepped, result) {
+                    self.ip = ip;
+                    return Err(self.runtime_error(&format!("Failed to write trace: {}", err)));
                 }
-                OpCode::Print => {
-                    let value = self.stack.pop().unwrap();
-                    println!("{}", value.format());
-                }
-                OpCode::GetGlobal(name) => match name as &str {
-                    "@balance" => {
-                        self.stack.push(Value::Number(self.balance));
-                    }
-                    "@debt" => {
-                        self.stack.push(Value::Number(self.debt));
-                    }
-                    "@won" => {
-                        self.stack.push(Value::Boolean(self.won_last_gamble));
-                    }
-                    "@can_work" => {
-                        self.stack.push(Value::Boolean(self.can_work));
-                    }
-                    _ => {
-                        let value = self.globals.get(name);
+            }
 
-                        if let Some(value) = value {
-                            self.stack.push(value.clone());
-                        } else {
-                            panic!("Accessing undefined variable '{}'", name);
-                        }
-                    }
-                },
-                OpCode::SetGlobal(name) => {
-                    let value = self.stack.pop().unwrap();
-                    self.globals.insert(name.to_string(), value);
-                }
-                OpCode::Add => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-
-                    if let (Value::Number(a), Value::Number(b)) = (&a, &b) {
-                        self.stack.push(Value::Number(a + b));
-                    } else if let (Value::String(a), Value::String(b)) = (&a, &b) {
-                        self.stack.push(Value::String(format!("{}{}", a, b)));
-                    } else if let (Value::String(a), Value::Number(b)) = (&a, &b) {
-                        self.stack.push(Value::String(format!("{}{}", a, b)));
-                    } else if let (Value::Number(a), Value::String(b)) = (&a, &b) {
-                        self.stack.push(Value::String(format!("{}{}", a, b)));
-                    } else {
-                        if a.is_truthy() {
-                            self.stack.push(a);
-                        } else {
-                            self.stack.push(b);
-                        }
-                        panic!("Operands must be numbers or strings");
-                    }
-                }
-                OpCode::Subtract => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
+            stepped?;
+        }
 
-                    if let (Value::Number(a), Value::Number(b)) = (a, b) {
-                        self.stack.push(Value::Number(a - b));
-                    } else {
-                        panic!("Operands must be numbers");
-                    }
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(err) = tracer.flush() {
+                return Err(MonopolangError::Runtime {
+                    message: format!("Failed to write trace: {}", err),
+                    span: self.spans.last().cloned().unwrap_or_default(),
+                });
+            }
+        }
+
+        if let Err(err) = self.output.flush() {
+            return Err(MonopolangError::Runtime {
+                message: format!("Failed to write output: {}", err),
+                span: self.spans.last().cloned().unwrap_or_default(),
+            });
+        }
+
+        Ok(())
+    }
+
+    // Executes the instruction at ip and moves ip to the next instruction to run
+    fn step(&mut self) -> Result<(), MonopolangError> {
+        match &self.code[self.ip] {
+            OpCode::Constant(index) => {
+                self.stack.push(self.read_constant(*index));
+            }
+            OpCode::Print => {
+                let value = self.stack.pop().unwrap();
+                if let Err(err) = writeln!(self.output, "{}", value.format()) {
+                    return Err(self.runtime_error(&format!("Failed to write output: {}", err)));
+                }
+            }
+            OpCode::GetGlobal(name) => match name as &str {
+                "@balance" => {
+                    self.stack.push(Value::Number(self.balance));
+                }
+                "@debt" => {
+                    self.stack.push(Value::Number(self.debt));
+                }
+                "@won" => {
+                    self.stack.push(Value::Boolean(self.won_last_gamble));
+                }
+                "@can_work" => {
+                    self.stack.push(Value::Boolean(self.can_work));
                 }
-                OpCode::Multiply => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
+                "@portfolio" => {
+                    let portfolio = self
+                        .stock_ownership
+                        .iter()
+                        .map(|(stock, shares)| (stock.clone(), Value::Number(*shares)))
+                        .collect();
+                    self.stack.push(Value::from_map(portfolio));
+                }
+                "@taxes_owed" => {
+                    let owed = self
+                        .taxes
+                        .owed(self.balance - self.debt, &self.economy.taxes);
+                    self.stack.push(Value::Number(owed));
+                }
+                "@next_tax_in" => {
+                    let remaining = self.taxes.operations_until_due(&self.economy.taxes);
+                    self.stack.push(Value::Number(remaining as f64));
+                }
+                _ => {
+                    let value = self.globals.get(name);
 
-                    if let (Value::Number(a), Value::Number(b)) = (a, b) {
-                        self.stack.push(Value::Number(a * b));
+                    if let Some(value) = value {
+                        self.stack.push(value.clone());
                     } else {
-                        panic!("Operands must be numbers");
+                        return Err(
+                            self.runtime_error(&format!("Accessing undefined variable '{}'", name))
+                        );
                     }
                 }
-                OpCode::Divide => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
+            },
+            // Procedures compiled before a constant was declared can't be checked by the compiler
+            OpCode::SetGlobal(name) if self.frozen.contains(name.as_str()) => {
+                return Err(self.runtime_error(&format!("Cannot assign to constant '{}'", name)));
+            }
+            OpCode::SetGlobal(name) => {
+                let value = self.stack.pop().unwrap();
+                self.globals.insert(name.to_string(), value);
+            }
+            OpCode::DefineConstant(name) => {
+                let value = self.stack.pop().unwrap();
+                self.frozen.insert(name.to_string());
+                self.globals.insert(name.to_string(), value);
+            }
+            OpCode::GetLocal(slot) => {
+                let value = self.stack[self.frame_base() + slot].clone();
+                self.stack.push(value);
+            }
+            OpCode::SetLocal(slot) => {
+                let value = self.stack.pop().unwrap();
+                let index = self.frame_base() + slot;
+                self.stack[index] = value;
+            }
+            OpCode::Add => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+
+                if let (Value::Number(a), Value::Number(b)) = (&a, &b) {
+                    self.stack.push(Value::Number(a + b));
+                } else if let (Value::String(a), Value::String(b)) = (&a, &b) {
+                    self.stack.push(Value::String(format!("{}{}", a, b)));
+                } else if let (Value::String(a), Value::Number(b)) = (&a, &b) {
+                    self.stack.push(Value::String(format!("{}{}", a, b)));
+                } else if let (Value::Number(a), Value::String(b)) = (&a, &b) {
+                    self.stack.push(Value::String(format!("{}{}", a, b)));
+                } else {
+                    return Err(self.runtime_error("Operands must be numbers or strings"));
+                }
+            }
+            OpCode::Subtract => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+
+                if let (Value::Number(a), Value::Number(b)) = (a, b) {
+                    self.stack.push(Value::Number(a - b));
+                } else {
+                    return Err(self.runtime_error("Operands must be numbers"));
+                }
+            }
+            OpCode::Multiply => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+
+                if let (Value::Number(a), Value::Number(b)) = (a, b) {
+                    self.stack.push(Value::Number(a * b));
+                } else {
+                    return Err(self.runtime_error("Operands must be numbers"));
+                }
+            }
+            OpCode::Divide => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+
+                if let (Value::Number(a), Value::Number(b)) = (a, b) {
+                    self.stack.push(Value::Number(a / b));
+                } else {
+                    return Err(self.runtime_error("Operands must be numbers"));
+                }
+            }
+            // Euclidean division, so (a div b) * b + a % b = a holds for negative operands too
+            OpCode::Modulo => {
+                let (a, b) = self.pop_divisible()?;
+                // Never negative, so x % 3 = 0 works for negative x too
+                self.stack.push(Value::Number(a.rem_euclid(b)));
+            }
+            OpCode::IntegerDivide => {
+                let (a, b) = self.pop_divisible()?;
+                self.stack.push(Value::Number(a.div_euclid(b)));
+            }
+            OpCode::Power => {
+                let (a, b) = self.pop_numbers()?;
+                self.stack.push(Value::Number(a.powf(b)));
+            }
+            OpCode::BitAnd => {
+                let (a, b) = self.pop_integers()?;
+                self.stack.push(Value::Number((a & b) as f64));
+            }
+            OpCode::BitOr => {
+                let (a, b) = self.pop_integers()?;
+                self.stack.push(Value::Number((a | b) as f64));
+            }
+            OpCode::BitXor => {
+                let (a, b) = self.pop_integers()?;
+                self.stack.push(Value::Number((a ^ b) as f64));
+            }
+            OpCode::ShiftLeft | OpCode::ShiftRight => {
+                let left = matches!(self.code[self.ip], OpCode::ShiftLeft);
+                let (a, b) = self.pop_integers()?;
 
-                    if let (Value::Number(a), Value::Number(b)) = (a, b) {
-                        self.stack.push(Value::Number(a / b));
-                    } else {
-                        panic!("Operands must be numbers");
-                    }
+                if !(0..64).contains(&b) {
+                    return Err(self.runtime_error("Shift amount must be between 0 and 63"));
                 }
-                OpCode::Negate => {
-                    let a = self.stack.pop().unwrap();
 
-                    if let Value::Number(a) = a {
-                        self.stack.push(Value::Number(-a));
-                    } else {
-                        panic!("Operand must be a number");
-                    }
+                let shifted = if left { a << b } else { a >> b };
+                self.stack.push(Value::Number(shifted as f64));
+            }
+            OpCode::Negate => {
+                let a = self.stack.pop().unwrap();
+
+                if let Value::Number(a) = a {
+                    self.stack.push(Value::Number(-a));
+                } else {
+                    return Err(self.runtime_error("Operand must be a number"));
+                }
+            }
+            OpCode::Not => {
+                let a = self.stack.pop().unwrap();
+                self.stack.push(Value::from_boolean(!a.is_truthy()));
+            }
+            OpCode::Equal => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+                self.stack.push(Value::from_boolean(a == b));
+            }
+            OpCode::NotEqual => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+                self.stack.push(Value::from_boolean(a != b));
+            }
+            OpCode::Greater => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+
+                if let (Value::Number(a), Value::Number(b)) = (a, b) {
+                    self.stack.push(Value::from_boolean(a > b));
+                } else {
+                    return Err(self.runtime_error("Operands must be numbers"));
                 }
-                OpCode::Not => {
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::from_boolean(!a.is_truthy()));
+            }
+            OpCode::GreaterEqual => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+
+                if let (Value::Number(a), Value::Number(b)) = (a, b) {
+                    self.stack.push(Value::from_boolean(a >= b));
+                } else {
+                    return Err(self.runtime_error("Operands must be numbers"));
                 }
-                OpCode::Equal => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::from_boolean(a == b));
+            }
+            OpCode::Less => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+
+                if let (Value::Number(a), Value::Number(b)) = (a, b) {
+                    self.stack.push(Value::from_boolean(a < b));
+                } else {
+                    return Err(self.runtime_error("Operands must be numbers"));
                 }
-                OpCode::NotEqual => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::from_boolean(a != b));
+            }
+            OpCode::LessEqual => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+
+                if let (Value::Number(a), Value::Number(b)) = (a, b) {
+                    self.stack.push(Value::from_boolean(a <= b));
+                } else {
+                    return Err(self.runtime_error("Operands must be numbers"));
                 }
-                OpCode::Greater => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
+            }
+            OpCode::JumpIfFalse(offset) => {
+                let condition = self.stack.pop().unwrap();
 
-                    if let (Value::Number(a), Value::Number(b)) = (a, b) {
-                        self.stack.push(Value::from_boolean(a > b));
-                    } else {
-                        panic!("Operands must be numbers");
-                    }
+                if !condition.is_truthy() {
+                    self.ip = *offset;
+                    return Ok(());
                 }
-                OpCode::GreaterEqual => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-
-                    if let (Value::Number(a), Value::Number(b)) = (a, b) {
-                        self.stack.push(Value::from_boolean(a >= b));
-                    } else {
-                        panic!("Operands must be numbers");
-                    }
+            }
+            OpCode::JumpIfFalseKeep(offset) => {
+                if !self.stack.last().unwrap().is_truthy() {
+                    self.ip = *offset;
+                    return Ok(());
                 }
-                OpCode::Less => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
 
-                    if let (Value::Number(a), Value::Number(b)) = (a, b) {
-                        self.stack.push(Value::from_boolean(a < b));
-                    } else {
-                        panic!("Operands must be numbers");
-                    }
+                self.stack.pop();
+            }
+            OpCode::JumpIfTrueKeep(offset) => {
+                if self.stack.last().unwrap().is_truthy() {
+                    self.ip = *offset;
+                    return Ok(());
                 }
-                OpCode::LessEqual => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
 
-                    if let (Value::Number(a), Value::Number(b)) = (a, b) {
-                        self.stack.push(Value::from_boolean(a <= b));
-                    } else {
-                        panic!("Operands must be numbers");
+                self.stack.pop();
+            }
+            OpCode::Jump(offset) => {
+                self.ip = *offset;
+                return Ok(());
+            }
+            OpCode::Call(name, argument_count)
+                if !self.procedures.contains_key(name) && self.natives.contains_key(name) =>
+            {
+                let name = name.clone();
+                self.call_native(&name, *argument_count)?;
+            }
+            OpCode::Call(name, argument_count) => {
+                let procedure = match self.procedures.get(name) {
+                    Some(procedure) => *procedure,
+                    None => {
+                        return Err(
+                            self.runtime_error(&format!("Calling undefined procedure '{}'", name))
+                        )
                     }
-                }
-                OpCode::And => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
+                };
 
-                    self.stack
-                        .push(Value::from_boolean(a.is_truthy() && b.is_truthy()));
+                if procedure.arity != *argument_count {
+                    return Err(self.runtime_error(&format!(
+                        "Procedure '{}' expects {} arguments but got {}",
+                        name, procedure.arity, argument_count
+                    )));
                 }
-                OpCode::Or => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
 
-                    self.stack
-                        .push(Value::from_boolean(a.is_truthy() || b.is_truthy()));
+                if self.frames.len() >= FRAMES_MAX {
+                    return Err(self.runtime_error(&format!(
+                        "Stack overflow while calling procedure '{}'",
+                        name
+                    )));
                 }
-                OpCode::JumpIfFalse(offset) => {
-                    let condition = self.stack.pop().unwrap();
 
-                    if !condition.is_truthy() {
-                        self.ip = *offset;
-                        continue;
+                // The arguments already on the stack become the procedure's first locals
+                self.frames.push(CallFrame {
+                    address: procedure.address,
+                    return_address: self.ip + 1,
+                    stack_base: self.stack.len() - argument_count,
+                });
+
+                self.ip = procedure.address;
+                return Ok(());
+            }
+            OpCode::Builtin(builtin, argument_count) => {
+                let (builtin, argument_count) = (*builtin, *argument_count);
+                self.call_builtin(builtin, argument_count)?;
+            }
+            OpCode::BuildList(length) => {
+                let items = self.stack.split_off(self.stack.len() - length);
+                self.stack.push(Value::from_list(items));
+            }
+            OpCode::FormatDecimals(decimals) => match self.stack.pop().unwrap() {
+                Value::Number(n) => self
+                    .stack
+                    .push(Value::String(format!("{:.*}", decimals, n))),
+                value => {
+                    return Err(self.runtime_error(&format!(
+                        "Only numbers can have decimals in a string, got {}",
+                        value.type_name()
+                    )))
+                }
+            },
+            OpCode::Input => {
+                let line = self.read_line()?;
+                self.stack.push(Value::String(line));
+            }
+            OpCode::InputNumber => {
+                let line = self.read_line()?;
+                match line.trim().parse::<f64>() {
+                    Ok(n) if n.is_finite() => self.stack.push(Value::Number(n)),
+                    _ => {
+                        return Err(
+                            self.runtime_error(&format!("Cannot convert '{}' to a number", line))
+                        )
                     }
                 }
-                OpCode::Jump(offset) => {
-                    self.ip = *offset;
-                    continue;
+            }
+            OpCode::Join(length) => {
+                let parts = self.stack.split_off(self.stack.len() - length);
+                let joined: String = parts.iter().map(Value::format).collect();
+                self.stack.push(Value::String(joined));
+            }
+            OpCode::BuildMap(length) => {
+                let values = self.stack.split_off(self.stack.len() - length * 2);
+                let mut entries = BTreeMap::new();
+
+                for entry in values.chunks(2) {
+                    match builtins::key(&entry[0]) {
+                        Ok(key) => entries.insert(key.to_string(), entry[1].clone()),
+                        Err(message) => return Err(self.runtime_error(&message)),
+                    };
                 }
-                OpCode::JumpForwardIfFalse(offset) => {
-                    let condition = self.stack.pop().unwrap();
 
-                    if !condition.is_truthy() {
-                        self.ip = (self.ip as isize + *offset) as usize;
-                        continue;
+                self.stack.push(Value::from_map(entries));
+            }
+            OpCode::Iterate => {
+                // Loops see the collection as it was when they started
+                let items = match self.stack.pop().unwrap() {
+                    Value::List(items) => items.borrow().clone(),
+                    Value::Map(entries) => entries
+                        .borrow()
+                        .keys()
+                        .map(|key| Value::String(key.clone()))
+                        .collect(),
+                    Value::String(s) => s.chars().map(|c| Value::String(c.to_string())).collect(),
+                    value => {
+                        return Err(
+                            self.runtime_error(&format!("Cannot loop over {}", value.type_name()))
+                        )
                     }
-                }
-                OpCode::JumpForward(offset) => {
-                    self.ip = (self.ip as isize + *offset) as usize;
-                    continue;
-                }
-                OpCode::ProcedureCall(name) => {
-                    // Replace the procedure call with the procedure's code
-                    let mut procedure = self.procedures.get(name).unwrap().clone();
-
-                    // Modify all the jumps in the procedure to be relative to the current IP
-                    for op in procedure.iter_mut() {
-                        match op {
-                            OpCode::Jump(offset) => {
-                                *offset = (*offset as isize + self.ip as isize) as usize;
-                            }
-                            OpCode::JumpIfFalse(offset) => {
-                                *offset = (*offset as isize + self.ip as isize) as usize;
-                            }
-                            _ => {}
-                        }
+                };
+
+                self.stack.push(Value::from_list(items));
+            }
+            OpCode::ForNext(offset) => {
+                let length = self.stack.len();
+
+                let next = match (&self.stack[length - 2], &self.stack[length - 1]) {
+                    (Value::List(items), Value::Number(index)) => {
+                        items.borrow().get(*index as usize).cloned()
                     }
+                    _ => unreachable!("For loop state is missing from the stack"),
+                };
 
-                    // Modify all future jumps (jumps pointing to IPs past the call) in the code to be +(procedure length - 1)
-                    // This is because the procedure call is replaced with the procedure's code and pushes the IP forward
-                    for op in self.code.iter_mut() {
-                        match op {
-                            OpCode::Jump(offset) => {
-                                if *offset > self.ip {
-                                    *offset =
-                                        (*offset as isize + procedure.len() as isize - 1) as usize;
-                                }
-                            }
-                            OpCode::JumpIfFalse(offset) => {
-                                if *offset > self.ip {
-                                    *offset =
-                                        (*offset as isize + procedure.len() as isize - 1) as usize;
-                                }
-                            }
-                            _ => {}
+                match next {
+                    Some(item) => {
+                        if let Value::Number(index) = &mut self.stack[length - 1] {
+                            *index += 1.0;
                         }
+                        self.stack.push(item);
+                    }
+                    None => {
+                        self.ip = *offset;
+                        return Ok(());
                     }
-
-                    self.code
-                        .splice(self.ip..self.ip + 1, procedure.iter().cloned());
-
-                    self.ip -= 1;
                 }
-                OpCode::Pop => {
-                    self.stack.pop();
+            }
+            OpCode::GetIndex => {
+                let index = self.stack.pop().unwrap();
+                let value = self.stack.pop().unwrap();
+
+                let item = match &value {
+                    Value::List(items) => {
+                        let items = items.borrow();
+                        builtins::index(&index, items.len()).map(|i| items[i].clone())
+                    }
+                    Value::String(s) => builtins::index(&index, s.chars().count())
+                        .map(|i| Value::String(s.chars().nth(i).unwrap().to_string())),
+                    Value::Map(entries) => builtins::key(&index).and_then(|key| {
+                        entries
+                            .borrow()
+                            .get(key)
+                            .cloned()
+                            .ok_or_else(|| format!("Key '{}' is not in the map", key))
+                    }),
+                    value => Err(format!("Cannot index into {}", value.type_name())),
+                };
+
+                match item {
+                    Ok(item) => self.stack.push(item),
+                    Err(message) => return Err(self.runtime_error(&message)),
                 }
-                OpCode::Cost(amount) => {
-                    self.balance -= amount;
+            }
+            OpCode::SetIndex => {
+                let item = self.stack.pop().unwrap();
+                let index = self.stack.pop().unwrap();
+                let value = self.stack.pop().unwrap();
+
+                let result = match &value {
+                    Value::List(_) | Value::Map(_) if item.reaches(&value) => {
+                        Err(format!("Cannot put a {} inside itself", value.type_name()))
+                    }
+                    Value::Map(entries) => {
+                        self.set_entry(entries, &index, item)?;
+                        Ok(())
+                    }
+                    Value::List(items) => {
+                        let mut items = items.borrow_mut();
+                        builtins::index(&index, items.len()).map(|i| items[i] = item)
+                    }
+                    value => Err(format!(
+                        "Cannot assign to an index of {}",
+                        value.type_name()
+                    )),
+                };
+
+                if let Err(message) = result {
+                    return Err(self.runtime_error(&message));
                 }
-                OpCode::Gamble => {
-                    let amount = self.stack.pop().unwrap();
+            }
+            OpCode::Return => {
+                let value = self.stack.pop().unwrap();
+                let frame = self.frames.pop().unwrap();
 
-                    if let Value::Number(amount) = amount {
-                        if amount > self.balance {
-                            panic!("Insufficient funds to gamble!");
-                        }
+                // Discard the procedure's locals, leaving only the return value
+                self.stack.truncate(frame.stack_base);
+                self.stack.push(value);
 
-                        let random = rand::random::<f64>();
+                self.ip = frame.return_address;
+                return Ok(());
+            }
+            OpCode::Pop => {
+                self.stack.pop();
+            }
+            OpCode::Cost(amount) => {
+                self.balance -= amount * self.price_level;
+            }
+            OpCode::Gamble => {
+                let amount = self.stack.pop().unwrap();
 
-                        if random < 0.5 {
-                            self.balance += amount;
-                            self.won_last_gamble = true;
-                        } else {
-                            self.balance -= amount;
-                            self.won_last_gamble = false;
-                        }
-                    } else {
-                        panic!("Operand must be a number");
+                if let Value::Number(amount) = amount {
+                    if amount > self.balance {
+                        return Err(self.economic_error("Insufficient funds to gamble!"));
                     }
-                }
-                OpCode::Loan => {
-                    let amount = self.stack.pop().unwrap();
 
-                    // A loan can only be taken out for up to (balance - debt) * 5
-                    // This is to prevent users from taking out absurd loans
+                    let random = self.rng.gen::<f64>();
 
-                    let max_loan = (self.balance - self.debt) * 5.0;
-
-                    if let Value::Number(amount) = amount {
-                        if amount > max_loan {
-                            panic!("Loan amount exceeds maximum loan amount");
-                        }
-
-                        self.debt += amount;
+                    if random < self.economy.gambling.win_chance {
                         self.balance += amount;
+                        self.won_last_gamble = true;
                     } else {
-                        panic!("Operand must be a number");
+                        self.balance -= amount;
+                        self.won_last_gamble = false;
                     }
+                } else {
+                    return Err(self.runtime_error("Operand must be a number"));
                 }
-                OpCode::Repay => {
-                    let amount = self.stack.pop().unwrap();
+            }
+            OpCode::Loan => {
+                let amount = self.stack.pop().unwrap();
 
-                    if let Value::Number(amount) = amount {
-                        if amount > self.balance {
-                            panic!("Insufficient funds to repay loan!");
-                        }
+                // A loan can only be taken out for up to (balance - debt) * max_multiplier
+                // This is to prevent users from taking out absurd loans
 
-                        if amount > self.debt {
-                            panic!("Repayment amount exceeds debt");
-                        }
+                let max_loan = (self.balance - self.debt) * self.economy.loans.max_multiplier;
 
-                        self.debt -= amount;
-                        self.balance -= amount;
-                    } else {
-                        panic!("Operand must be a number");
+                if let Value::Number(amount) = amount {
+                    if amount > max_loan {
+                        return Err(self.economic_error("Loan amount exceeds maximum loan amount"));
                     }
-                }
-                OpCode::Work => {
-                    if self.can_work {
-                        self.balance += (self.balance * 0.001).max(100.0); // 0.1% of balance or 100, whichever is greater
-                        self.can_work = false;
 
-                        // Sleep for 300ms to simulate work
-                        std::thread::sleep(std::time::Duration::from_millis(300));
-                    } else {
-                        panic!("You are on a work cooldown!");
-                    }
+                    self.debt += amount;
+                    self.balance += amount;
+                } else {
+                    return Err(self.runtime_error("Operand must be a number"));
                 }
-                OpCode::Buy => {
-                    let name = self.stack.pop().unwrap();
-                    let amount = self.stack.pop().unwrap();
-
-                    if let (Value::String(name), Value::Number(amount)) = (name, amount) {
-                        // If the stock doesn't exist, create it with a random price
-                        if !self.stock_prices.contains_key(&name) {
-                            self.create_stock(&name);
-                        }
-
-                        let price = self.stock_prices.get(&name).unwrap();
+            }
+            OpCode::Repay => {
+                let amount = self.stack.pop().unwrap();
 
-                        if amount * price > self.balance {
-                            panic!("Insufficient funds to buy stock!");
-                        }
+                if let Value::Number(amount) = amount {
+                    if amount > self.balance {
+                        return Err(self.economic_error("Insufficient funds to repay loan!"));
+                    }
 
-                        self.balance -= amount * price;
-                        self.stock_ownership
-                            .entry(name.clone())
-                            .and_modify(|owned| *owned += amount as u32)
-                            .or_insert(amount as u32);
+                    if amount > self.debt {
+                        return Err(self.economic_error("Repayment amount exceeds debt"));
                     }
+
+                    self.debt -= amount;
+                    self.balance -= amount;
+                } else {
+                    return Err(self.runtime_error("Operand must be a number"));
+                }
+            }
+            OpCode::Work => {
+                if self.can_work {
+                    // A fraction of the balance or the minimum pay, whichever is greater
+                    let work = &self.economy.work;
+                    self.balance += (self.balance * work.pay_rate).max(work.minimum_pay);
+                    self.can_work = false;
+
+                    // Sleep to simulate work
+                    std::thread::sleep(std::time::Duration::from_millis(work.duration_ms));
+                } else {
+                    return Err(self.economic_error("You are on a work cooldown!"));
+                }
+            }
+            OpCode::Buy => {
+                let (name, amount) = self.pop_trade()?;
+                let cost = amount * self.stock_price(&name);
+
+                if cost > self.balance {
+                    return Err(self.economic_error(&format!(
+                        "Insufficient funds to buy {} shares of {}, they cost ${}",
+                        amount, name, cost
+                    )));
                 }
-                OpCode::Sell => {
-                    let name = self.stack.pop().unwrap();
-                    let amount = self.stack.pop().unwrap();
 
-                    if let (Value::String(name), Value::Number(amount)) = (name, amount) {
-                        if !self.stock_prices.contains_key(&name) {
-                            panic!("Stock does not exist!");
-                        }
+                self.balance -= cost;
+                *self.stock_ownership.entry(name).or_insert(0.0) += amount;
+            }
+            OpCode::Sell => {
+                let (name, amount) = self.pop_trade()?;
+                let owned = self.stock_ownership.get(&name).copied().unwrap_or(0.0);
+
+                if amount > owned + SHARE_EPSILON {
+                    return Err(self.economic_error(&format!(
+                        "Cannot sell {} shares of {}, you only own {}",
+                        amount, name, owned
+                    )));
+                }
 
-                        let price = self.stock_prices.get(&name).unwrap();
+                self.balance += amount * self.stock_price(&name);
 
-                        if !self.stock_ownership.contains_key(&name) {
-                            panic!("You do not own any of this stock!");
-                        }
+                // Drop positions that have been sold off, rather than keeping zero (or rounding error) shares around
+                if owned - amount <= SHARE_EPSILON {
+                    self.stock_ownership.remove(&name);
+                } else {
+                    self.stock_ownership.insert(name, owned - amount);
+                }
+            }
+            OpCode::GetStockPrice(name) => {
+                let price = self.stock_price(&name.clone());
+                self.stack.push(Value::Number(price));
+            }
+        }
 
-                        let owned = self.stock_ownership.get(&name).unwrap();
+        if self.balance <= 0.0 {
+            return Err(self.economic_error("Insufficient funds!"));
+        }
 
-                        if amount > *owned as f64 {
-                            panic!("You do not own enough of this stock!");
-                        }
+        self.ip += 1;
 
-                        self.balance += amount * price;
-                        self.stock_ownership
-                            .entry(name.clone())
-                            .and_modify(|owned| *owned -= amount as u32);
-                    }
-                }
-                OpCode::GetStockPrice(name) => {
-                    let name = &name.clone(); // Fixes borrow checker issue
+        Ok(())
+    }
+}
 
-                    if !self.stock_prices.contains_key(name) {
-                        self.create_stock(name);
-                    }
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "balance: ${:.2}", self.balance)?;
+        writeln!(f, "debt: ${:.2}", self.debt)?;
+        writeln!(f, "taxes paid: ${:.2}", self.taxes_paid)?;
+        write!(f, "operations: {}", self.operations)?;
 
-                    let price = self.stock_prices.get(name).unwrap();
-                    self.stack.push(Value::Number(*price));
-                }
-            }
+        for holding in &self.portfolio {
+            write!(f, "\n{}", holding)?;
+        }
 
-            if self.balance <= 0.0 {
-                panic!("Insufficient funds!");
-            }
+        Ok(())
+    }
+}
 
-            self.ip += 1;
-        }
+impl fmt::Display for Holding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} shares at ${:.2} (${:.2})",
+            self.stock,
+            self.shares,
+            self.price,
+            self.shares * self.price
+        )
     }
 }
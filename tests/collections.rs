@@ -0,0 +1,63 @@
+mod common;
+
+use common::{run, runtime_error};
+use monopolang::{economy::Costs, Economy, Interpreter, MonopolangError};
+
+#[test]
+fn lists_cannot_be_pushed_into_themselves() {
+    let message = runtime_error("set l -> [1]\ncall push(l, l)\nprint l");
+    assert_eq!(message, "Cannot push a list into itself");
+}
+
+#[test]
+fn lists_cannot_be_stored_inside_themselves() {
+    let message = runtime_error("set l -> [1]\nset l[0] -> l\nprint l = l");
+    assert_eq!(message, "Cannot put a list inside itself");
+}
+
+#[test]
+fn lists_cannot_contain_themselves_through_another_list() {
+    let message = runtime_error("set a -> []\nset b -> [a]\ncall push(a, b)\nprint a");
+    assert_eq!(message, "Cannot push a list into itself");
+}
+
+#[test]
+fn the_same_list_can_be_stored_twice() {
+    let stdout =
+        run("set a -> [1]\nset b -> [a, a]\ncall push(b, a)\nprint b\nprint a = a").unwrap();
+    assert_eq!(stdout, "[[1], [1], [1]]\ntrue\n");
+}
+
+#[test]
+fn maps_cannot_be_stored_inside_themselves() {
+    let message = runtime_error("set m -> {}\nset m[\"m\"] -> m\nprint m");
+    assert_eq!(message, "Cannot put a map inside itself");
+
+    let message = runtime_error("set m -> {}\nset l -> [m]\nset m[\"l\"] -> l\nprint m");
+    assert_eq!(message, "Cannot put a map inside itself");
+}
+
+#[test]
+fn adding_a_map_entry_can_bankrupt_the_program() {
+    // Creating the map and the assignment leave exactly enough for the new entry, reads are free here
+    let economy = Economy {
+        starting_balance: 4.5,
+        costs: Costs {
+            read: 0.0,
+            ..Costs::default()
+        },
+        ..Economy::default()
+    };
+
+    let result = Interpreter::new("set m -> {}\nset m[\"a\"] -> 1")
+        .economy(economy)
+        .seed(1)
+        .run();
+
+    match result {
+        Err(MonopolangError::Economic { message, .. }) => {
+            assert_eq!(message, "Insufficient funds!")
+        }
+        other => panic!("expected bankruptcy, got {:?}", other),
+    }
+}
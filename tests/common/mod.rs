@@ -0,0 +1,105 @@
+// Helpers shared by the integration tests, each test file only uses some of them
+#![allow(dead_code)]
+
+use std::{
+    cell::RefCell,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use monopolang::{Interpreter, MonopolangError, RunReport};
+
+// Runs a program with a fixed seed, so results don't depend on luck
+pub fn report(source: &str) -> Result<RunReport, MonopolangError> {
+    Interpreter::new(source).seed(1).run()
+}
+
+// Runs a program and gives back what it printed
+pub fn run(source: &str) -> Result<String, MonopolangError> {
+    report(source).map(|report| report.stdout)
+}
+
+// Runs a program and gives back the balance it ended with
+pub fn balance(source: &str) -> f64 {
+    report(source).unwrap().summary.balance
+}
+
+// Balances go through floating point arithmetic, so they are compared with a tolerance
+pub fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+pub fn runtime_error(source: &str) -> String {
+    match run(source) {
+        Err(MonopolangError::Runtime { message, .. }) => message,
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+// A fresh directory under the system temp dir, removed again when dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    // Names only need to be unique within a test binary, the process id separates parallel runs
+    pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let path = std::env::temp_dir().join(format!("monopolang-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        let directory = TempDir { path };
+        for (file, contents) in files {
+            directory.write(file, contents);
+        }
+        directory
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path.join(path)
+    }
+
+    // Writes a file, creating the directories leading to it
+    pub fn write(&self, file: &str, contents: &str) {
+        let path = self.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// A writer that can be handed to the interpreter while the test keeps a handle to read it back
+#[derive(Clone, Default)]
+pub struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl SharedOutput {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::{assert_close, balance, report};
+use monopolang::Economy;
+
+// Each branch prints, so the test can see which one ran
+fn chain(value: u32) -> String {
+    format!(
+        "set x -> {}\nif x = 1 then\nprint \"one\"\nelif x = 2 then\nprint \"two\"\nelif x = 3 then\nprint \"three\"\nelse\nprint \"other\"\nend",
+        value
+    )
+}
+
+#[test]
+fn elif_chains_charge_only_the_conditions_checked() {
+    let costs = Economy::default().costs;
+    let start = balance("set x -> 0");
+
+    // Every checked condition costs the if price and a read of x, then the branch prints
+    for (value, output, checked) in [
+        (1, "one", 1.0),
+        (2, "two", 2.0),
+        (3, "three", 3.0),
+        (4, "other", 3.0),
+    ] {
+        let report = report(&chain(value)).unwrap();
+        assert_eq!(report.stdout, format!("{}\n", output));
+        assert_close(
+            report.summary.balance,
+            start - checked * (costs.if_statement + costs.read) - costs.print,
+        );
+    }
+}
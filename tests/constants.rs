@@ -0,0 +1,65 @@
+mod common;
+
+use common::{assert_close, balance, report};
+use monopolang::{Economy, MonopolangError};
+
+#[test]
+fn constants_are_folded_at_compile_time() {
+    let report = report("const A -> 2 ** 3\nconst B -> A * 2 + 1\nprint B").unwrap();
+    assert_eq!(report.stdout, "17\n");
+
+    // The power and arithmetic were computed by the compiler, so they cost nothing at runtime
+    assert_eq!(
+        balance("const A -> 2 ** 3\nconst B -> A * 2 + 1"),
+        balance("const A -> 8\nconst B -> 17")
+    );
+}
+
+#[test]
+fn constants_depending_on_variables_are_computed_when_declared() {
+    let costs = Economy::default().costs;
+    let operators = Economy::default().operators;
+
+    let report = report("set x -> 2\nconst C -> x ** 3\nprint C").unwrap();
+    assert_eq!(report.stdout, "8\n");
+    assert_close(
+        report.summary.balance,
+        balance("set x -> 2\nconst C -> 8\nprint C") - operators.power - costs.read,
+    );
+}
+
+#[test]
+fn constants_cannot_be_assigned() {
+    match report("const A -> 1\nset A -> 2") {
+        Err(MonopolangError::Compile { message, .. }) => {
+            assert_eq!(message, "Cannot assign to constant 'A'")
+        }
+        other => panic!("expected a compile error, got {:?}", other),
+    }
+}
+
+#[test]
+fn defining_a_constant_costs_a_one_time_fee() {
+    let economy = Economy::default();
+    assert_close(
+        balance("const A -> 1"),
+        economy.starting_balance - economy.costs.constant,
+    );
+}
+
+#[test]
+fn constants_are_cheaper_to_read_than_variables() {
+    let costs = Economy::default().costs;
+
+    // Only the prints are paid for when reading a constant
+    assert_close(
+        balance("const A -> 1\nprint A\nprint A"),
+        balance("const A -> 1") - 2.0 * costs.print,
+    );
+    // A variable read costs extra every time
+    assert_close(
+        balance("set v -> 1\nprint v\nprint v"),
+        balance("set v -> 1") - 2.0 * (costs.print + costs.read),
+    );
+    assert!(costs.read > 0.0);
+}
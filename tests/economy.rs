@@ -0,0 +1,114 @@
+mod common;
+
+use common::TempDir;
+use monopolang::{economy::PRESETS, Economy, Interpreter, MonopolangError};
+
+#[test]
+fn an_invalid_economy_is_rejected_before_running() {
+    let mut economy = Economy::default();
+    economy.debt_collection.interval = 0;
+
+    match Interpreter::new("print 1").economy(economy).seed(1).run() {
+        Err(error @ MonopolangError::Config { .. }) => {
+            assert_eq!(
+                error.message(),
+                "Invalid economy: debt_collection.interval must be at least 1"
+            );
+            assert_eq!(error.span(), None);
+            // Nothing in the program is to blame, so no source line is shown
+            assert_eq!(
+                error.render("main.mp", "print 1"),
+                "configuration error: Invalid economy: debt_collection.interval must be at least 1\n"
+            );
+        }
+        other => panic!("expected a configuration error, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_seed_replays_gambling_and_stocks_exactly() {
+    let source = "range i from 1 to 5 do\n    gamble 10\nend\nbuy \"AAPL\" 0.01\nprint @balance";
+    let run = || Interpreter::new(source).seed(42).run().unwrap();
+
+    let first = run();
+    assert_eq!(first, run());
+    assert_eq!(first.summary.portfolio.len(), 1);
+}
+
+#[test]
+fn every_preset_is_valid() {
+    for name in PRESETS {
+        let economy = Economy::preset(name).unwrap();
+        assert_eq!(economy.validate(), Ok(()), "preset {}", name);
+    }
+}
+
+#[test]
+fn profiles_load_from_toml_and_json() {
+    let directory = TempDir::new(
+        "economy-profiles",
+        &[
+            (
+                "tight.toml",
+                "starting_balance = 50\n\n[costs]\nprint = 5\n",
+            ),
+            (
+                "tight.json",
+                r#"{"starting_balance": 50, "costs": {"print": 5}}"#,
+            ),
+        ],
+    );
+
+    for file in ["tight.toml", "tight.json"] {
+        let economy = Economy::load(&directory.join(file)).unwrap();
+        assert_eq!(economy.starting_balance, 50.0);
+        assert_eq!(economy.costs.print, 5.0);
+        assert_eq!(
+            economy.costs.assignment,
+            Economy::default().costs.assignment
+        );
+    }
+}
+
+#[test]
+fn the_example_profile_loads() {
+    let path =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/economies/challenge.toml");
+    assert!(Economy::load(&path).is_ok());
+}
+
+#[test]
+fn bad_profiles_are_rejected() {
+    let profiles = [
+        (
+            "starting_balance = nan",
+            "starting_balance must be a finite number",
+        ),
+        ("starting_balance = -5", "starting_balance must be positive"),
+        ("[costs]\nprint = -1", "costs.print can't be negative"),
+        (
+            "[operators]\npower = inf",
+            "operators.power must be a finite number",
+        ),
+        (
+            "[stocks]\ninitial_price = -10",
+            "stocks.initial_price can't be negative",
+        ),
+        ("[taxes]\ninterval = 0", "taxes.interval must be at least 1"),
+        (
+            "[gambling]\nwin_chance = 2",
+            "gambling.win_chance must be between 0 and 1",
+        ),
+    ];
+
+    for (i, (profile, message)) in profiles.into_iter().enumerate() {
+        let file = format!("bad{}.toml", i);
+        let directory = TempDir::new(&format!("economy-bad-{}", i), &[(&file, profile)]);
+        assert_eq!(
+            Economy::load(&directory.join(&file)),
+            Err(message.to_string()),
+            "{}",
+            profile
+        );
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{assert_close, balance};
+use monopolang::{Economy, Interpreter, MonopolangError, RunReport};
+
+fn run_with_input(source: &str, input: &str) -> Result<RunReport, MonopolangError> {
+    Interpreter::new(source)
+        .input(Cursor::new(input.to_string()))
+        .seed(1)
+        .run()
+}
+
+#[test]
+fn input_reads_lines_without_their_endings() {
+    let report = run_with_input(
+        "set a -> input\nset b -> input\nprint a + \"|\" + b",
+        "Ada\r\nLovelace\n",
+    )
+    .unwrap();
+    assert_eq!(report.stdout, "Ada|Lovelace\n");
+}
+
+#[test]
+fn input_number_parses_the_line() {
+    let report = run_with_input("print input_number * 2", " 21 \n").unwrap();
+    assert_eq!(report.stdout, "42\n");
+
+    match run_with_input("print input_number", "lots\n") {
+        Err(MonopolangError::Runtime { message, .. }) => {
+            assert_eq!(message, "Cannot convert 'lots' to a number")
+        }
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn every_byte_read_is_charged() {
+    let report = run_with_input("set a -> input", "abcd\n").unwrap();
+    let costs = Economy::default().costs;
+
+    // Five bytes including the line ending
+    assert_close(
+        report.summary.balance,
+        balance("set a -> \"abcd\"") - 5.0 * costs.input,
+    );
+}
+
+#[test]
+fn reading_past_the_end_of_input_is_an_io_error() {
+    let cases = [
+        ("set a -> input\nset b -> input", "only\n"),
+        ("print input_number", ""),
+    ];
+
+    for (source, input) in cases {
+        match run_with_input(source, input) {
+            Err(error @ MonopolangError::Io { .. }) => {
+                assert_eq!(error.message(), "Reached the end of input");
+                assert_eq!(error.exit_code(), 74);
+            }
+            other => panic!("expected an io error, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn without_an_input_reader_there_is_nothing_to_read() {
+    match Interpreter::new("print input").seed(1).run() {
+        Err(MonopolangError::Io { message, .. }) => assert_eq!(message, "Reached the end of input"),
+        other => panic!("expected an io error, got {:?}", other),
+    }
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::report;
+
+const NOISY: &str = "proc noisy() do\n    print \"called\"\n    return \"right\"\nend\n";
+
+fn balance(source: &str) -> f64 {
+    report(&format!("{}{}", NOISY, source))
+        .unwrap()
+        .summary
+        .balance
+}
+
+#[test]
+fn a_false_left_operand_skips_the_right_of_and() {
+    let report = report(&format!("{}print false and noisy()", NOISY)).unwrap();
+    assert_eq!(report.stdout, "false\n");
+    assert_eq!(
+        report.summary.balance,
+        balance("print false"),
+        "the skipped call must not be charged"
+    );
+}
+
+#[test]
+fn a_true_left_operand_skips_the_right_of_or() {
+    let report = report(&format!("{}print true or noisy()", NOISY)).unwrap();
+    assert_eq!(report.stdout, "true\n");
+    assert_eq!(report.summary.balance, balance("print true"));
+}
+
+#[test]
+fn the_deciding_operand_is_the_result() {
+    let report = report(&format!(
+        "{}print true and noisy()\nprint get({{}}, \"k\") or \"fallback\"\nprint 0 and noisy()",
+        NOISY
+    ))
+    .unwrap();
+    assert_eq!(report.stdout, "called\nright\nfallback\n0\n");
+    assert!(report.summary.balance < balance("print true\nprint get({}, \"k\")\nprint 0"));
+}
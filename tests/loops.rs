@@ -0,0 +1,87 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn break_and_continue_leave_for_each_bodies_with_locals() {
+    let source = "
+set out -> \"\"
+for x in [1, 2, 3, 4] do
+    let double -> x * 2
+    if x = 2 then
+        continue
+    end
+    if x = 4 then
+        break
+    end
+    let text -> \"{double},\"
+    set out -> out + text
+end
+print out
+print \"after\"";
+    assert_eq!(run(source).unwrap(), "2,6,\nafter\n");
+}
+
+#[test]
+fn return_leaves_a_for_each_inside_a_procedure() {
+    let source = "
+proc find(items, wanted) do
+    for item in items do
+        let found -> item = wanted
+        if found then
+            return item
+        end
+    end
+    return \"none\"
+end
+print find([\"a\", \"b\", \"c\"], \"b\")
+print find([\"a\"], \"z\")
+print len([find([\"x\"], \"x\"), 2])";
+    assert_eq!(run(source).unwrap(), "b\nnone\n2\n");
+}
+
+#[test]
+fn continue_in_a_range_still_runs_the_step() {
+    let source = "
+range i from 0 to 10 by 3 do
+    if i = 3 then
+        continue
+    end
+    print i
+end";
+    assert_eq!(run(source).unwrap(), "0\n6\n9\n");
+}
+
+#[test]
+fn locals_shadow_in_nested_blocks() {
+    let source = "
+proc show() do
+    let x -> 1
+    if true then
+        let x -> 2
+        if true then
+            let x -> 3
+            print x
+        end
+        print x
+    end
+    print x
+end
+call show()";
+    assert_eq!(run(source).unwrap(), "3\n2\n1\n");
+}
+
+#[test]
+fn for_each_variables_survive_recursive_calls() {
+    let source = "
+proc walk(name, depth) do
+    for part in [name] do
+        if depth > 0 then
+            call walk(\"inner\", depth - 1)
+        end
+        print part
+    end
+end
+call walk(\"outer\", 1)";
+    assert_eq!(run(source).unwrap(), "inner\nouter\n");
+}
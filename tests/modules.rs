@@ -0,0 +1,97 @@
+mod common;
+
+use std::fs;
+
+use common::TempDir;
+use monopolang::{Interpreter, MonopolangError};
+
+fn compile_error(directory: &TempDir) -> String {
+    let main = directory.join("main.mp");
+    let source = fs::read_to_string(&main).unwrap();
+
+    match Interpreter::new(source).path(main).seed(1).run() {
+        Err(MonopolangError::Compile { message, .. }) => message,
+        other => panic!("expected a compile error, got {:?}", other),
+    }
+}
+
+#[test]
+fn module_procedures_are_called_by_module_name() {
+    let directory = TempDir::new(
+        "modules-call",
+        &[
+            ("main.mp", "import \"greet.mp\"\ncall greet.hi()"),
+            ("greet.mp", "proc hi() do\nprint \"hi\"\nend"),
+        ],
+    );
+    let main = directory.join("main.mp");
+    let source = fs::read_to_string(&main).unwrap();
+
+    let report = Interpreter::new(source).path(main).seed(1).run().unwrap();
+    assert_eq!(report.stdout, "hi\n");
+}
+
+#[test]
+fn module_names_follow_the_identifier_rules() {
+    let directory = TempDir::new(
+        "modules-digits",
+        &[("main.mp", "import \"v2.mp\""), ("v2.mp", "print 1")],
+    );
+    assert_eq!(
+        compile_error(&directory),
+        "Cannot import 'v2.mp', 'v2' is not a valid module name"
+    );
+}
+
+#[test]
+fn importing_the_main_file_back_is_a_cycle() {
+    let directory = TempDir::new(
+        "modules-cycle",
+        &[
+            ("main.mp", "import \"cyc.mp\""),
+            ("cyc.mp", "import \"main.mp\""),
+        ],
+    );
+    assert_eq!(
+        compile_error(&directory),
+        "Import cycle: main.mp -> cyc.mp -> main.mp"
+    );
+}
+
+#[test]
+fn errors_in_modules_point_at_the_module_file() {
+    let directory = TempDir::new(
+        "modules-error-file",
+        &[
+            ("main.mp", "import \"broken.mp\""),
+            ("broken.mp", "print missing"),
+        ],
+    );
+    let main = directory.join("main.mp");
+    let source = fs::read_to_string(&main).unwrap();
+
+    let error = Interpreter::new(source)
+        .path(main)
+        .seed(1)
+        .run()
+        .unwrap_err();
+    let file = error
+        .span()
+        .unwrap()
+        .file
+        .expect("the error should name the module");
+    assert!(file.ends_with("broken.mp"), "{}", file);
+    assert!(error.render("main.mp", "").contains("1 | print missing"));
+}
+
+#[test]
+fn module_names_cannot_be_reserved_words() {
+    let directory = TempDir::new(
+        "modules-keyword",
+        &[("main.mp", "import \"end.mp\""), ("end.mp", "print 1")],
+    );
+    assert_eq!(
+        compile_error(&directory),
+        "Cannot import 'end.mp', 'end' is a reserved word and can't name a module"
+    );
+}
@@ -0,0 +1,33 @@
+use monopolang::{Interpreter, MonopolangError, Value};
+
+#[test]
+fn natives_are_called_like_procedures() {
+    let report = Interpreter::new("print double(21)")
+        .native("double", 1, 1.0, |args| match &args[0] {
+            Value::Number(n) => Ok(Value::Number(n * 2.0)),
+            _ => Err("expected a number".to_string()),
+        })
+        .seed(1)
+        .run()
+        .unwrap();
+    assert_eq!(report.stdout, "42\n");
+}
+
+#[test]
+fn natives_cannot_shadow_builtins() {
+    let result = Interpreter::new("print len([1])")
+        .native("len", 1, 0.0, |_| Ok(Value::Number(0.0)))
+        .seed(1)
+        .run();
+
+    match result {
+        Err(error @ MonopolangError::Config { .. }) => {
+            assert_eq!(
+                error.message(),
+                "Cannot register native 'len', a builtin has that name"
+            );
+            assert_eq!(error.span(), None);
+        }
+        other => panic!("expected a configuration error, got {:?}", other),
+    }
+}
@@ -0,0 +1,26 @@
+mod common;
+
+use common::{run, runtime_error};
+
+#[test]
+fn modulo_and_integer_division_agree_for_negative_operands() {
+    let source = "
+print 7 % -3
+print 7 div -3
+print -7 % 3
+print -7 div 3
+print -7 % -3
+print -7 div -3";
+    assert_eq!(run(source).unwrap(), "1\n-2\n2\n-3\n2\n3\n");
+
+    for (a, b) in [(7, 3), (7, -3), (-7, 3), (-7, -3)] {
+        let source = format!("print ({a} div {b}) * {b} + {a} % {b}");
+        assert_eq!(run(&source).unwrap(), format!("{}\n", a), "{} and {}", a, b);
+    }
+}
+
+#[test]
+fn modulo_and_integer_division_reject_a_zero_divisor() {
+    assert_eq!(runtime_error("print 5 % 0"), "Division by zero");
+    assert_eq!(runtime_error("print 5 div 0"), "Division by zero");
+}
@@ -0,0 +1,45 @@
+use monopolang::{Economy, Interpreter, MonopolangError};
+
+// Calls cost money, so deep recursion needs a rich program
+fn run_rich(source: &str) -> Result<String, MonopolangError> {
+    let economy = Economy {
+        starting_balance: 1e9,
+        ..Economy::default()
+    };
+
+    Interpreter::new(source)
+        .economy(economy)
+        .seed(1)
+        .run()
+        .map(|report| report.stdout)
+}
+
+#[test]
+fn deep_recursion_works() {
+    let source = "
+proc sum(n) do
+    if n = 0 then
+        return 0
+    end
+    return n + sum(n - 1)
+end
+print sum(4000)";
+    assert_eq!(run_rich(source).unwrap(), "8002000\n");
+}
+
+#[test]
+fn unbounded_recursion_is_a_clean_stack_overflow() {
+    let source = "
+proc down(n) do
+    return down(n + 1)
+end
+print down(0)";
+
+    match run_rich(source) {
+        Err(MonopolangError::Runtime { message, span }) => {
+            assert_eq!(message, "Stack overflow while calling procedure 'down'");
+            assert_eq!(span.line, 3);
+        }
+        other => panic!("expected a stack overflow, got {:?}", other),
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::SharedOutput;
+use monopolang::{Economy, Repl};
+
+fn session(input: &str) -> String {
+    let output = SharedOutput::default();
+    Repl::new(Economy::default(), 1, None).run(Cursor::new(input.to_string()), output.clone());
+    output.text()
+}
+
+#[test]
+fn blocks_continue_over_several_lines() {
+    let output =
+        session("proc twice(x) do\nreturn x * 2\nend\nif twice(2) = 4 then\nprint \"four\"\nend\n");
+
+    // Inner lines get the continuation prompt, and nothing runs before the block is closed
+    assert_eq!(output.matches("... ").count(), 4);
+    assert!(output.ends_with("... four\n> \n"), "{:?}", output);
+}
+
+#[test]
+fn an_error_does_not_end_the_session() {
+    let output = session("print missing\nset x -> 1\nprint x + 1\n");
+
+    assert!(output.contains("runtime error: Accessing undefined variable 'missing'"));
+    assert!(output.contains("2\n"), "{:?}", output);
+}
+
+#[test]
+fn state_and_commands_carry_over_between_inputs() {
+    let output = session("set x -> 40\nprint x + 2\n:ops\n:quit\nprint \"never\"\n");
+
+    assert!(output.contains("42\n"));
+    assert!(output.contains("operations: "));
+    assert!(!output.contains("never"));
+}
+
+#[test]
+fn programs_read_from_the_same_input() {
+    let output = session("set name -> input\nworld\nprint \"hello \" + name\n");
+    assert!(output.contains("hello world\n"), "{:?}", output);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::fs;
+
+use common::TempDir;
+use monopolang::{Interpreter, Sandbox};
+
+// A `box` directory for the sandbox, with a secret next to it that must stay out of reach
+fn scratch(name: &str) -> TempDir {
+    let directory = TempDir::new(&format!("sandbox-{}", name), &[("secret.txt", "secret")]);
+    fs::create_dir(directory.join("box")).unwrap();
+    directory
+}
+
+fn outside(result: Result<String, String>, path: &str) {
+    assert_eq!(
+        result,
+        Err(format!("Path '{}' is outside of the sandbox", path))
+    );
+}
+
+#[test]
+fn files_inside_the_sandbox_can_be_read_and_written() {
+    let directory = scratch("inside");
+    let sandbox = Sandbox::new(directory.join("box")).unwrap();
+
+    sandbox.write("notes.txt", "a", false).unwrap();
+    sandbox.write("./notes.txt", "b", true).unwrap();
+    assert_eq!(sandbox.read("notes.txt"), Ok("ab".to_string()));
+}
+
+#[test]
+fn parent_directories_are_rejected() {
+    let directory = scratch("parent");
+    let sandbox = Sandbox::new(directory.join("box")).unwrap();
+
+    outside(sandbox.read("../secret.txt"), "../secret.txt");
+    outside(sandbox.read("sub/../../secret.txt"), "sub/../../secret.txt");
+    assert!(sandbox.write("../new.txt", "x", false).is_err());
+    assert!(!directory.join("new.txt").exists());
+}
+
+#[test]
+fn absolute_paths_are_rejected() {
+    let directory = scratch("absolute");
+    let sandbox = Sandbox::new(directory.join("box")).unwrap();
+    let secret = directory.join("secret.txt");
+    let path = secret.to_str().unwrap();
+
+    outside(sandbox.read(path), path);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_directories_cannot_lead_outside() {
+    let directory = scratch("symlinked");
+    std::os::unix::fs::symlink(directory.path(), directory.join("box/link")).unwrap();
+    let sandbox = Sandbox::new(directory.join("box")).unwrap();
+
+    outside(sandbox.read("link/secret.txt"), "link/secret.txt");
+    assert!(sandbox.write("link/new.txt", "x", false).is_err());
+    assert!(!directory.join("new.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_symlinks_cannot_lead_outside() {
+    let directory = scratch("dangling");
+    std::os::unix::fs::symlink(
+        directory.join("missing.txt"),
+        directory.join("box/link.txt"),
+    )
+    .unwrap();
+    let sandbox = Sandbox::new(directory.join("box")).unwrap();
+
+    assert_eq!(
+        sandbox.write("link.txt", "x", false),
+        Err("Path 'link.txt' is outside of the sandbox".to_string())
+    );
+    assert!(!directory.join("missing.txt").exists());
+}
+
+#[test]
+fn file_transfers_are_billed_per_started_kilobyte() {
+    let directory = scratch("billing");
+    for (name, bytes) in [("one.txt", 1), ("kilobyte.txt", 1024), ("more.txt", 1025)] {
+        fs::write(directory.join("box").join(name), "x".repeat(bytes)).unwrap();
+    }
+
+    let balance = |name: &str| {
+        let source = format!("set contents -> read_file(\"{}\")", name);
+        Interpreter::new(source)
+            .sandbox(Sandbox::new(directory.join("box")).unwrap())
+            .seed(1)
+            .run()
+            .unwrap()
+            .summary
+            .balance
+    };
+
+    let file = monopolang::Economy::default().costs.file;
+    assert_eq!(balance("one.txt"), balance("kilobyte.txt"));
+    assert_eq!(balance("kilobyte.txt") - balance("more.txt"), file);
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::{assert_close, balance, report, run};
+use monopolang::{Economy, MonopolangError};
+
+#[test]
+fn part_of_a_position_can_be_sold() {
+    let price: f64 = run("print $AAPL").unwrap().trim().parse().unwrap();
+    let trade = Economy::default().costs.trade;
+
+    let report = report("set p -> $AAPL\nbuy \"AAPL\" 1.5\nsell \"AAPL\" 0.5").unwrap();
+    let baseline = balance("set p -> $AAPL");
+
+    let holding = &report.summary.portfolio[0];
+    assert_eq!(holding.stock, "AAPL");
+    assert_close(holding.shares, 1.0);
+    assert_close(holding.price, price);
+
+    // Both trades pay the broker fee, and the shares kept are paid for
+    assert_close(report.summary.balance, baseline - 2.0 * trade - price);
+}
+
+#[test]
+fn selling_everything_closes_the_position() {
+    let report = report("buy \"AAPL\" 0.25\nsell \"AAPL\" 0.25").unwrap();
+    assert!(report.summary.portfolio.is_empty());
+}
+
+#[test]
+fn selling_more_than_is_held_is_refused() {
+    match report("buy \"AAPL\" 0.5\nsell \"AAPL\" 2") {
+        Err(MonopolangError::Economic { message, .. }) => {
+            assert_eq!(message, "Cannot sell 2 shares of AAPL, you only own 0.5")
+        }
+        other => panic!("expected an economic error, got {:?}", other),
+    }
+}
+
+#[test]
+fn the_summary_values_the_portfolio_at_current_prices() {
+    let report = report("buy \"AAPL\" 0.5\nbuy \"MSFT\" 1\nbuy \"AAPL\" 0.25").unwrap();
+    let portfolio = &report.summary.portfolio;
+
+    // Sorted by name, with fractional buys added up
+    assert_eq!(portfolio.len(), 2);
+    assert_eq!(portfolio[0].stock, "AAPL");
+    assert_close(portfolio[0].shares, 0.75);
+    assert_eq!(portfolio[1].stock, "MSFT");
+
+    let aapl = &portfolio[0];
+    assert_eq!(
+        aapl.to_string(),
+        format!(
+            "AAPL: 0.75 shares at ${:.2} (${:.2})",
+            aapl.price,
+            aapl.shares * aapl.price
+        )
+    );
+    assert!(report
+        .summary
+        .to_string()
+        .ends_with(&portfolio[1].to_string()));
+}
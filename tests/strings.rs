@@ -0,0 +1,52 @@
+mod common;
+
+use common::run;
+use monopolang::{MonopolangError, Span};
+
+#[test]
+fn strings_can_span_lines() {
+    assert_eq!(run("print \"hello\nworld\"").unwrap(), "hello\nworld\n");
+}
+
+#[test]
+fn columns_restart_after_a_line_break_in_a_string() {
+    match run("print \"a\nb\" ~") {
+        Err(MonopolangError::Lex { span, .. }) => assert_eq!(
+            span,
+            Span {
+                line: 2,
+                column: 4,
+                length: 1,
+                file: None
+            }
+        ),
+        other => panic!("expected a lex error, got {:?}", other),
+    }
+}
+
+#[test]
+fn interpolated_strings_can_span_lines() {
+    assert_eq!(run("print \"a {1} b\nc {3}\"").unwrap(), "a 1 b\nc 3\n");
+}
+
+#[test]
+fn embedded_expressions_can_contain_strings() {
+    let source = "set m -> {\"k\": 2}\nprint \"v={get(m, \"k\")}, {\"s\"}\"";
+    assert_eq!(run(source).unwrap(), "v=2, s\n");
+}
+
+#[test]
+fn embedded_expressions_can_contain_map_literals() {
+    assert_eq!(
+        run("print \"{ {\"x\": 1.5}[\"x\"]:.2} {{literal}}\"").unwrap(),
+        "1.50 {literal}\n"
+    );
+}
+
+#[test]
+fn an_unclosed_brace_points_at_the_string() {
+    match run("print \"a {1\"") {
+        Err(MonopolangError::Lex { span, .. }) => assert_eq!((span.line, span.column), (1, 7)),
+        other => panic!("expected a lex error, got {:?}", other),
+    }
+}
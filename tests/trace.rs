@@ -0,0 +1,34 @@
+mod common;
+
+use common::SharedOutput;
+use monopolang::{Interpreter, TraceFilter, TraceFormat, Tracer};
+
+fn trace(source: &str, filters: Vec<TraceFilter>) -> Vec<String> {
+    let output = SharedOutput::default();
+    let tracer = Tracer::new(filters, TraceFormat::Text, Box::new(output.clone()));
+    let _ = Interpreter::new(source).tracer(tracer).seed(1).run();
+
+    output.text().lines().map(str::to_string).collect()
+}
+
+#[test]
+fn instructions_are_traced_with_their_procedure() {
+    let lines = trace(
+        "proc greet() do\nprint \"hi\"\nend\ncall greet()",
+        vec![TraceFilter::Procedure("greet".to_string())],
+    );
+    assert!(!lines.is_empty());
+    assert!(lines.iter().all(|line| line.contains("procedure: greet,")));
+}
+
+#[test]
+fn the_failing_instruction_is_traced() {
+    let lines = trace(
+        "proc boom() do\nprint missing\nend\ncall boom()",
+        Vec::new(),
+    );
+    let last = lines.last().unwrap();
+
+    assert!(last.contains("procedure: boom,"), "{}", last);
+    assert!(last.contains("GetGlobal(\"missing\")"), "{}", last);
+}